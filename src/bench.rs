@@ -0,0 +1,230 @@
+// `nonlinear_opt bench`: runs each strategy on each benchmark function with each budget and seed,
+// and summarizes the results in a table. Configure it with `config::bench_config`.
+
+use crate::config::{bench_config, default_config, BenchConfig};
+use crate::files::{write_string, WriteMode};
+use crate::master;
+use crate::objective::Objective;
+use crate::utils::get_distance_of_params;
+
+pub struct BenchRow {
+    pub strategy: String,
+    pub benchmark: String,
+    pub budget: usize,
+    pub runs: usize,
+    pub median_final_loss: f64,
+
+    // l2 distance between the best parameters and `Benchmark::optimum`
+    pub median_distance_to_optimum: f64,
+
+    // among the successful runs, None if no run succeeded
+    pub median_evaluations_to_tolerance: Option<usize>,
+
+    // 0 ~ 1
+    pub success_rate: f64,
+}
+
+pub fn run_bench() {
+    let BenchConfig {
+        strategies,
+        benchmarks,
+        seeds,
+        budgets,
+        tolerance,
+        write_csv_to,
+        write_markdown_to,
+    } = bench_config();
+
+    let mut rows = vec![];
+
+    for strategy in strategies.iter() {
+        for benchmark in benchmarks.iter() {
+            for budget in budgets.iter() {
+                let mut final_losses = vec![];
+                let mut distances_to_optimum = vec![];
+                let mut evaluations_to_tolerance = vec![];
+
+                for seed in seeds.iter() {
+                    let mut config = default_config();
+                    (strategy.configure)(&mut config);
+
+                    config.objective = Objective::Benchmark(*benchmark);
+                    config.max_evaluations = Some(*budget);
+                    config.target_loss = Some(tolerance);
+                    config.seed = Some(*seed);
+                    config.visualize = false;
+                    config.write_logs_to = None;
+                    config.listen_for_workers = None;
+
+                    let summary = master::run(config);
+                    let distance_to_optimum = get_distance_of_params(
+                        &summary.best_params,
+                        &benchmark.optimum(summary.best_params.len()),
+                    );
+
+                    println!(
+                        "{} | {} | budget: {budget} | seed: {seed} | loss: {} | distance to optimum: {distance_to_optimum} | evaluations: {} | {:.1}s",
+                        strategy.name,
+                        benchmark.name(),
+                        summary.best_loss,
+                        summary.evaluations,
+                        summary.elapsed.as_secs_f32(),
+                    );
+
+                    final_losses.push(summary.best_loss);
+                    distances_to_optimum.push(distance_to_optimum);
+
+                    if let Some(evaluations) = summary.evaluations_to_target {
+                        evaluations_to_tolerance.push(evaluations);
+                    }
+                }
+
+                rows.push(BenchRow {
+                    strategy: strategy.name.clone(),
+                    benchmark: benchmark.name().to_string(),
+                    budget: *budget,
+                    runs: seeds.len(),
+                    median_final_loss: median(&mut final_losses).unwrap_or(f64::NAN),
+                    median_distance_to_optimum: median(&mut distances_to_optimum).unwrap_or(f64::NAN),
+                    success_rate: evaluations_to_tolerance.len() as f64 / seeds.len().max(1) as f64,
+                    median_evaluations_to_tolerance: median(&mut evaluations_to_tolerance),
+                });
+            }
+        }
+    }
+
+    println!("\n{}", render_markdown(&rows));
+
+    if let Some(path) = &write_csv_to {
+        write_string(path, &render_csv(&rows), WriteMode::CreateOrTruncate).unwrap();
+    }
+
+    if let Some(path) = &write_markdown_to {
+        write_string(path, &render_markdown(&rows), WriteMode::CreateOrTruncate).unwrap();
+    }
+}
+
+pub fn render_csv(rows: &[BenchRow]) -> String {
+    let mut lines = vec![String::from("strategy,benchmark,budget,runs,median_final_loss,median_distance_to_optimum,median_evaluations_to_tolerance,success_rate")];
+
+    for row in rows.iter() {
+        lines.push(format!(
+            "{},{},{},{},{},{},{},{:.3}",
+            csv_escape(&row.strategy),
+            csv_escape(&row.benchmark),
+            row.budget,
+            row.runs,
+            row.median_final_loss,
+            row.median_distance_to_optimum,
+            row.median_evaluations_to_tolerance.map(|n| n.to_string()).unwrap_or_default(),
+            row.success_rate,
+        ));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+pub fn render_markdown(rows: &[BenchRow]) -> String {
+    let mut lines = vec![
+        String::from("| strategy | benchmark | budget | runs | median final loss | median distance to optimum | median evaluations to tolerance | success rate |"),
+        String::from("|---|---|---|---|---|---|---|---|"),
+    ];
+
+    for row in rows.iter() {
+        lines.push(format!(
+            "| {} | {} | {} | {} | {:.6} | {:.4} | {} | {:.0}% |",
+            row.strategy,
+            row.benchmark,
+            row.budget,
+            row.runs,
+            row.median_final_loss,
+            row.median_distance_to_optimum,
+            row.median_evaluations_to_tolerance.map(|n| n.to_string()).unwrap_or_else(|| String::from("-")),
+            row.success_rate * 100.0,
+        ));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+
+    else {
+        s.to_string()
+    }
+}
+
+// the lower one if the length is even
+fn median<T: Copy + PartialOrd>(v: &mut [T]) -> Option<T> {
+    if v.is_empty() {
+        return None;
+    }
+
+    v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    Some(v[(v.len() - 1) / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(strategy: &str, median_evaluations_to_tolerance: Option<usize>, success_rate: f64) -> BenchRow {
+        BenchRow {
+            strategy: strategy.to_string(),
+            benchmark: String::from("sphere"),
+            budget: 1000,
+            runs: 4,
+            median_final_loss: 0.125,
+            median_distance_to_optimum: 0.5,
+            median_evaluations_to_tolerance,
+            success_rate,
+        }
+    }
+
+    #[test]
+    fn medians() {
+        for (v, expected) in [
+            (vec![], None),
+            (vec![3.0], Some(3.0)),
+            (vec![3.0, 1.0, 2.0], Some(2.0)),
+
+            // the lower one of the 2 in the middle
+            (vec![4.0, 1.0, 3.0, 2.0], Some(2.0)),
+            (vec![1.0, 1.0], Some(1.0)),
+
+            (vec![f64::INFINITY, 1.0, 2.0], Some(2.0)),
+        ] {
+            assert_eq!(median(&mut v.clone()), expected, "{v:?}");
+        }
+
+        assert_eq!(median(&mut [7usize, 5, 9, 1]), Some(5));
+
+        // NaN doesn't panic
+        assert!(median(&mut [f64::NAN]).unwrap().is_nan());
+    }
+
+    #[test]
+    fn tables() {
+        let rows = [row("default", Some(300), 0.75), row("low, \"moment\"", None, 0.0)];
+
+        assert_eq!(render_csv(&rows), concat!(
+            "strategy,benchmark,budget,runs,median_final_loss,median_distance_to_optimum,median_evaluations_to_tolerance,success_rate\n",
+            "default,sphere,1000,4,0.125,0.5,300,0.750\n",
+            "\"low, \"\"moment\"\"\",sphere,1000,4,0.125,0.5,,0.000\n",
+        ));
+
+        assert_eq!(render_markdown(&rows), concat!(
+            "| strategy | benchmark | budget | runs | median final loss | median distance to optimum | median evaluations to tolerance | success rate |\n",
+            "|---|---|---|---|---|---|---|---|\n",
+            "| default | sphere | 1000 | 4 | 0.125000 | 0.5000 | 300 | 75% |\n",
+            "| low, \"moment\" | sphere | 1000 | 4 | 0.125000 | 0.5000 | - | 0% |\n",
+        ));
+
+        // only the header without rows
+        assert_eq!(render_csv(&[]).lines().count(), 1);
+        assert_eq!(render_markdown(&[]).lines().count(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+// The master writes its states to `Config.checkpoint`, so that a later run can continue from them.
+//
+// It's a text file. Floats are written in `f64`, which round-trips `f32` and `f64` exactly.
+//
+// ```nohighlight
+// dimension 4
+//
+// state
+// loss 0.25
+// parameters 0.1 -0.2 0.3 0.4
+// prev_step 0.01 0.02 -0.03 0.04
+//
+// state
+// loss 0.5
+// parameters 0.5 0.5 0.5 0.5
+// ```
+//
+// `prev_step` is omitted if the state doesn't have one. Empty lines and lines that start with `#` are ignored.
+//
+// The master writes the whole file to `<path>.tmp` and then renames it to `<path>`, so a crash while writing
+// doesn't destroy the previous checkpoint.
+
+use crate::files::{read_string, rename, write_string, FileError, WriteMode};
+use crate::float::Float;
+use crate::state::State;
+
+pub struct StateCheckpoint {
+    pub loss: f64,
+    pub parameters: Vec<f64>,
+    pub prev_step: Option<Vec<f64>>,
+}
+
+pub enum CheckpointError {
+    File(FileError),
+    Syntax {
+        path: String,
+
+        // 1-based
+        line: usize,
+        message: String,
+    },
+    DimensionMismatch {
+        path: String,
+        expected: usize,
+        got: usize,
+    },
+    Empty {
+        path: String,
+    },
+}
+
+impl CheckpointError {
+    pub fn render_error(&self) -> String {
+        match self {
+            CheckpointError::File(e) => e.render_error(),
+            CheckpointError::Syntax { path, line, message } => format!("`{path}`, line {line}: {message}"),
+            CheckpointError::DimensionMismatch { path, expected, got } => format!(
+                "`{path}` has {got} parameters, but the objective has {expected}"
+            ),
+            CheckpointError::Empty { path } => format!("`{path}` doesn't have any state"),
+        }
+    }
+}
+
+pub fn save_checkpoint<T: Float>(path: &str, dimension: usize, states: &[State<T>]) -> Result<(), FileError> {
+    let mut lines = vec![format!("dimension {dimension}")];
+
+    for state in states.iter() {
+        lines.push(String::new());
+        lines.push(String::from("state"));
+        lines.push(format!("loss {}", state.loss.to_f64()));
+        lines.push(format!("parameters {}", render_floats(&state.parameters)));
+
+        if let Some(prev_step) = &state.prev_step {
+            lines.push(format!("prev_step {}", render_floats(prev_step)));
+        }
+    }
+
+    let temp_path = format!("{path}.tmp");
+    write_string(&temp_path, &(lines.join("\n") + "\n"), WriteMode::CreateOrTruncate)?;
+    rename(&temp_path, path)
+}
+
+// It fails if any vector in the file doesn't have `dimension` elements.
+pub fn load_checkpoint(path: &str, dimension: usize) -> Result<Vec<StateCheckpoint>, CheckpointError> {
+    let s = read_string(path).map_err(CheckpointError::File)?;
+    let syntax_error = |line: usize, message: String| CheckpointError::Syntax { path: path.to_string(), line, message };
+
+    let mut file_dimension = None;
+    let mut states: Vec<StateCheckpoint> = vec![];
+
+    for (index, line) in s.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+        match key {
+            "dimension" => {
+                file_dimension = Some(value.trim().parse::<usize>().map_err(
+                    |_| syntax_error(line_no, format!("`{value}` is not a dimension"))
+                )?);
+            },
+            "state" => {
+                states.push(StateCheckpoint {
+                    loss: f64::INFINITY,
+                    parameters: vec![],
+                    prev_step: None,
+                });
+            },
+            "loss" | "parameters" | "prev_step" => {
+                let Some(state) = states.last_mut() else {
+                    return Err(syntax_error(line_no, format!("`{key}` before `state`")));
+                };
+
+                match key {
+                    "loss" => {
+                        state.loss = value.trim().parse::<f64>().map_err(
+                            |_| syntax_error(line_no, format!("`{value}` is not a number"))
+                        )?;
+                    },
+                    "parameters" => {
+                        state.parameters = parse_floats(value).map_err(|message| syntax_error(line_no, message))?;
+                    },
+                    _ => {
+                        state.prev_step = Some(parse_floats(value).map_err(|message| syntax_error(line_no, message))?);
+                    },
+                }
+            },
+            _ => {
+                return Err(syntax_error(line_no, format!("unknown key `{key}`")));
+            },
+        }
+    }
+
+    if states.is_empty() {
+        return Err(CheckpointError::Empty { path: path.to_string() });
+    }
+
+    let lengths = file_dimension.into_iter().chain(states.iter().flat_map(
+        |state| [Some(state.parameters.len()), state.prev_step.as_ref().map(|s| s.len())].into_iter().flatten()
+    ));
+
+    for got in lengths {
+        if got != dimension {
+            return Err(CheckpointError::DimensionMismatch { path: path.to_string(), expected: dimension, got });
+        }
+    }
+
+    Ok(states)
+}
+
+impl StateCheckpoint {
+    pub fn into_state<T: Float>(self, id: usize) -> State<T> {
+        State::new(
+            id,
+            self.parameters.into_iter().map(T::from_f64).collect(),
+            self.prev_step.map(|s| s.into_iter().map(T::from_f64).collect()),
+            T::from_f64(self.loss),
+        )
+    }
+}
+
+fn render_floats(v: &[impl Float]) -> String {
+    v.iter().map(|n| n.to_f64().to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_floats(s: &str) -> Result<Vec<f64>, String> {
+    s.split_whitespace().map(
+        |n| n.parse::<f64>().map_err(|_| format!("`{n}` is not a number"))
+    ).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::{exists, remove_file, temp_path};
+
+    fn state<T: Float>(id: usize, parameters: Vec<T>, prev_step: Option<Vec<T>>, loss: T) -> State<T> {
+        State {
+            id,
+            parameters: parameters.into(),
+            prev_step: prev_step.map(|s| s.into()),
+            loss,
+            successful_turns: 0,
+            failed_turns: 0,
+            evaluations: 0,
+            last_updated_at: None,
+            losses_over_time: vec![],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round_trip");
+        let states = vec![
+            state(0, vec![0.1f32, -2.5e-7, 3.0], Some(vec![0.01, 0.02, -0.03]), 0.25),
+            state(1, vec![1.0 / 3.0, 0.0, -1.0], None, f32::INFINITY),
+        ];
+
+        // the second save replaces the first one, through `<path>.tmp`
+        save_checkpoint(&path, 3, &states[..1]).unwrap();
+        save_checkpoint(&path, 3, &states).unwrap();
+        assert!(!exists(&format!("{path}.tmp")));
+
+        let loaded = load_checkpoint(&path, 3).map_err(|e| e.render_error()).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+
+        for (loaded, state) in loaded.into_iter().zip(states.iter()) {
+            let loaded = loaded.into_state::<f32>(state.id);
+
+            // bit-exact
+            assert_eq!(loaded.parameters, state.parameters);
+            assert_eq!(loaded.prev_step, state.prev_step);
+            assert_eq!(loaded.loss, state.loss);
+        }
+    }
+
+    #[test]
+    fn dimension_mismatch() {
+        let path = temp_path("dimension_mismatch");
+        save_checkpoint(&path, 3, &[state(0, vec![1.0f64, 2.0, 3.0], None, 1.0)]).unwrap();
+        let result = load_checkpoint(&path, 4);
+        remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(CheckpointError::DimensionMismatch { expected: 4, got: 3, .. })));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let path = temp_path("syntax_errors");
+
+        for (content, error_line) in [
+            ("dimension 2\nloss 1.0\n", 2),
+            ("dimension 2\nstate\nparameters 1.0 x\n", 3),
+            ("dimension 2\n\n# comment\nstate\nweights 1.0 2.0\n", 5),
+        ] {
+            write_string(&path, content, WriteMode::CreateOrTruncate).unwrap();
+
+            match load_checkpoint(&path, 2) {
+                Err(CheckpointError::Syntax { line, .. }) => { assert_eq!(line, error_line); },
+                _ => panic!("`{content}` has to be a syntax error"),
+            }
+        }
+
+        write_string(&path, "dimension 2\n", WriteMode::CreateOrTruncate).unwrap();
+        assert!(matches!(load_checkpoint(&path, 2), Err(CheckpointError::Empty { .. })));
+
+        remove_file(&path).unwrap();
+    }
+}
@@ -1,55 +1,243 @@
-// either `f32` or `f64`
-pub type ParamType = f32;
+use crate::groups::ParameterGroup;
+use crate::objective::Objective;
+use crate::samples::benchmarks::Benchmark;
+use crate::sampling::InitStrategy;
+use crate::scaling::Scaling;
+use crate::subspace::Subspace;
+use std::time::Duration;
 
-// parameters.len()
-pub const PARAM_SIZE: usize = 32;
+// the default `penalty_loss`
+// `f` may return NaN or infinity: such candidates are rejected
+pub const VERY_BIG_LOSS: f64 = 3e20;
 
-// make sure that `f` never returns a value greater than this
-pub const VERY_BIG_LOSS: ParamType = 3e20;
+pub struct Config {
+    pub objective: Objective,
+    pub dimension: Option<usize>,
+    pub num_workers: usize,
+    pub iter_per_worker: usize,
+    pub initial_l2_norm: f64,
+    pub initial_step_size: f64,
+    pub step_moment: f64,
+    pub visualize: bool,
+    pub write_logs_to: Option<String>,
+    pub remove_existing_log_file: bool,
+    pub max_evaluations: Option<usize>,
+    pub target_loss: Option<f64>,
+    pub eval_timeout: Option<Duration>,
+    pub penalty_loss: f64,
+    pub listen_for_workers: Option<String>,
+    pub seed: Option<u64>,
+    pub checkpoint: Option<String>,
+    pub resume_from_checkpoint: bool,
+    pub initial_points: Option<String>,
+    pub mix_with_random_params: bool,
+    pub init_strategy: InitStrategy,
+    pub scaling: Scaling,
+    pub parameter_groups: Vec<ParameterGroup>,
+    pub subspace: Subspace,
+}
 
 // modify this function to change configs
-pub fn default_config() -> (
-    usize,
-    usize,
-    ParamType,
-    ParamType,
-    ParamType,
-    bool,
-    Option<String>,
-    bool,
-) {
-    (
-        8,    // number of parallel workers (it has to be at least 2)
-        512,  // iterations per worker
-        1.0,  // l2 norm of initial random parameters
-        0.5,  // l2 norm of the first step
+pub fn default_config() -> Config {
+    Config {
+        // what to optimize (see `Objective` for the other options)
+        // the objective also decides the precision of the optimizer: `Native` is `f32`,
+        // and `NativeF64` and the others are `f64`
+        objective: Objective::Native(f),
+
+        // a rust function in `f64`: the optimizer runs in `f64`, too (`f` can also be rewritten to take `&[f64]`)
+        // objective: Objective::NativeF64(|parameters| parameters.iter().map(|p| (p - 1.0) * (p - 1.0)).sum()),
+
+        // an external program that reads parameters from stdin and prints losses to stdout
+        // objective: Objective::External {
+        //     command: String::from("python3"),
+        //     args: vec![String::from("./objective.py")],
+        // },
+
+        // an expression (see `expr.rs` for the syntax)
+        // objective: Objective::Expression(String::from("sum((x[i] - 1)^2) + 0.1 * x[0] * x[1]")),
+
+        // a standard test function (see `samples/benchmarks.rs`)
+        // objective: Objective::Benchmark(Benchmark::Rosenbrock),
+
+        // an LSTM that predicts the next byte of a text corpus (see `samples/text.rs`)
+        // objective: Objective::NativeWithDimension { f: crate::samples::text::f, dimension: crate::samples::text::dimension },
+
+        // a force-directed layout of the graph in `./graph.dot` (see `samples/graph.rs`)
+        // objective: Objective::NativeWithDimension { f: crate::samples::graph::f, dimension: crate::samples::graph::dimension },
+
+        // the number of parameters
+        // None: the objective's own dimension (only `Objective::NativeWithDimension` and `Objective::Plugin` know their dimensions)
+        // if the objective knows its dimension, this has to be None or the same value
+        dimension: Some(32),
+
+        num_workers: 8,  // number of parallel workers (it has to be at least 1)
+        iter_per_worker: 512,  // iterations per worker
+        initial_l2_norm: 1.0,  // l2 norm of initial random parameters
+        initial_step_size: 0.5,  // l2 norm of the first step
 
         // step moment
         // if it's 1, the step is never updated
         // if it's 0, the previous step is completely ignored
-        0.65,
+        step_moment: 0.65,
+
+        visualize: true,
+
+        write_logs_to: Some(String::from("./log.txt")),  // write logs to here
+        remove_existing_log_file: true,
+
+        // the optimizer stops after calling `f` this many times (None: runs forever)
+        max_evaluations: None,
+
+        // the optimizer stops when a loss reaches this value (None: runs forever)
+        // it's also used to measure how many evaluations it takes to reach the target
+        target_loss: None,
+
+        // if a call to `f` takes longer than this, the worker gives up the call and uses `penalty_loss`
+        // a timed-out call keeps running in the background until it returns (there's no way to kill a thread):
+        // each timeout abandons one OS thread for as long as `f` stays hung, and the next call starts a new one
+        // `Objective::External` kills the process instead, so it doesn't leak threads
+        // None: no timeout (it's faster because `f` runs on the worker thread)
+        eval_timeout: None,
+
+        // loss of a call to `f` that panicked or timed out
+        // if it's NaN or infinity, such calls are rejected like any other non-finite loss
+        penalty_loss: VERY_BIG_LOSS,
+
+        // None: workers are threads of this process
+        // Some(address): the master listens at the address, and waits for `num_workers` remote workers
+        //                run `nonlinear_opt worker <address>` to start a remote worker
+        listen_for_workers: None,
+
+        // seed of the random number generators of the workers (None: random seeds)
+        // runs with the same seed are similar, but not identical: the master handles
+        // the results in the order they arrive (with 1 worker and `max_evaluations`, they're identical)
+        seed: None,
+
+        // the master writes its states to this file every 10 seconds, and at the end
+        // see `checkpoint.rs` for the format
+        checkpoint: None,
 
-        true,  // visualize
+        // if it's true and `checkpoint` exists, the run starts warm: the master skips the random phase
+        // and continues from the states in the file
+        // the dimension of the checkpoint has to be the same as the objective's
+        resume_from_checkpoint: false,
 
-        Some(String::from("./log.txt")),  // write logs to here
-        true,  // remove existing log file
-    )
+        // the optimizer starts from the parameters in this file, instead of random ones
+        // each vector becomes a state (see `points.rs` for the formats)
+        // every vector has to have as many parameters as the objective
+        // it's ignored if the optimizer resumes from `checkpoint`
+        initial_points: None,
+
+        // if it's true, the optimizer also runs the random phase, and adds its states to
+        // the initial points (it's always true if `initial_points` is None)
+        mix_with_random_params: false,
+
+        // how the workers draw the candidates of the random phase (see `sampling.rs`)
+        // e.g. `InitStrategy::LatinHypercube { lower: -1.0, upper: 1.0 }`
+        init_strategy: InitStrategy::RandomL2Norm,
+
+        // per-parameter scales of the random steps (see `scaling.rs`)
+        // e.g. `Scaling::Learned { decay: 0.9, min_scale: 0.05 }`
+        scaling: Scaling::Isotropic,
+
+        // named slices of the parameters with their own step size, momentum and freeze flag (see `groups.rs`)
+        // e.g. `vec![ParameterGroup { step_size: Some(0.01), ..ParameterGroup::new("bias", 0..16) }]`
+        parameter_groups: vec![],
+
+        // each candidate perturbs a few coordinates, instead of every parameter (see `subspace.rs`)
+        // e.g. `Subspace::RandomSubset { size: 64 }` for millions of parameters
+        subspace: Subspace::Full,
+    }
+}
+
+// a set of overrides applied to `default_config()` when running `nonlinear_opt bench`
+pub struct Strategy {
+    pub name: String,
+    pub configure: fn(&mut Config),
+}
+
+pub struct BenchConfig {
+    pub strategies: Vec<Strategy>,
+    pub benchmarks: Vec<Benchmark>,
+    pub seeds: Vec<u64>,
+
+    // `max_evaluations` of each run
+    pub budgets: Vec<usize>,
+
+    // a run succeeds if its loss reaches this value (all the benchmarks' minima are 0)
+    pub tolerance: f64,
+
+    pub write_csv_to: Option<String>,
+    pub write_markdown_to: Option<String>,
+}
+
+// modify this function to change what `nonlinear_opt bench` runs
+// `objective`, `max_evaluations`, `target_loss`, `seed`, `visualize`, `write_logs_to` and
+// `listen_for_workers` are set by the bench runner
+pub fn bench_config() -> BenchConfig {
+    BenchConfig {
+        strategies: vec![
+            Strategy {
+                name: String::from("default"),
+                configure: |_| {},
+            },
+            Strategy {
+                name: String::from("low moment"),
+                configure: |config| { config.step_moment = 0.3; },
+            },
+            Strategy {
+                name: String::from("small steps"),
+                configure: |config| {
+                    config.initial_step_size = 0.05;
+                    config.iter_per_worker = 128;
+                },
+            },
+            Strategy {
+                name: String::from("latin hypercube"),
+                configure: |config| { config.init_strategy = InitStrategy::LatinHypercube { lower: -2.0, upper: 2.0 }; },
+            },
+            Strategy {
+                name: String::from("learned scaling"),
+                configure: |config| { config.scaling = Scaling::Learned { decay: 0.9, min_scale: 0.05 }; },
+            },
+        ],
+        benchmarks: vec![
+            Benchmark::Sphere,
+            Benchmark::Rosenbrock,
+            Benchmark::Rastrigin,
+            Benchmark::Ackley,
+        ],
+        seeds: vec![0, 1, 2, 3, 4],
+        budgets: vec![10_000, 100_000],
+        tolerance: 1e-2,
+        write_csv_to: Some(String::from("./bench.csv")),
+        write_markdown_to: Some(String::from("./bench.md")),
+    }
 }
 
 // Function that you're optimizing
-pub fn f(parameters: &[ParamType]) -> ParamType {  // returns loss
+#[allow(unused_variables)]
+pub fn f(parameters: &[f32]) -> f32 {  // returns loss
     // impl body
     todo!()
 }
 
 // dependencies of the default visualizer
+use crate::float::Float;
 use crate::state::State;
+use crate::stats::Stats;
 
 // if config.visualize is true, this function is called every iteration (about 1s)
-pub fn visualizer(states: &[State]) {
+pub fn visualizer<T: Float>(states: &[State<T>], stats: &Stats, groups: &[ParameterGroup]) {
+    // with `samples::graph`, this also writes a python script per state that draws the layout
+    // return crate::samples::graph::visualizer(states, stats, groups);
+
     clearscreen::clear().unwrap();
 
     for state in states.iter() {
-        println!("\n{}", state.pretty_print());
+        println!("\n{}", state.pretty_print(groups));
     }
+
+    println!("\n{}", stats.pretty_print());
 }
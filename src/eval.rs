@@ -0,0 +1,342 @@
+use crate::float::Float;
+use crate::objective::{LossFunction, Objective};
+use crate::stats::EvalStats;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub enum EvalError {
+    Panicked(String),
+    TimedOut(Duration),
+
+    // an external objective failed (it's counted as a panic)
+    Failed(String),
+
+    // `f` returned NaN or infinity
+    // they're converted to `f64`, so that the error doesn't depend on the precision
+    NonFinite {
+        loss: f64,
+        parameters: Vec<f64>,
+    },
+}
+
+impl EvalError {
+    pub fn render_error(&self) -> String {
+        match self {
+            EvalError::Panicked(msg) => format!("`f` panicked: {msg}"),
+            EvalError::TimedOut(timeout) => format!("`f` did not return in {}ms", timeout.as_millis()),
+            EvalError::Failed(msg) => format!("`f` failed: {msg}"),
+            EvalError::NonFinite { loss, parameters } => format!("`f` returned {loss}, parameters: {parameters:?}"),
+        }
+    }
+}
+
+// Each worker owns one. It calls `f`, but a panic or a timeout of `f` doesn't kill the worker:
+// the evaluation gets `penalty_loss` instead, and the error is reported to the master.
+// A non-finite loss (NaN or infinity) rejects the candidate: `eval` returns None.
+pub struct Evaluator<T: Float> {
+    objective: Objective,
+
+    // it's instantiated lazily, and dropped after a failure so that the next call gets a fresh one
+    loss_function: Option<LossFunction>,
+
+    timeout: Option<Duration>,
+    penalty_loss: T,
+
+    // `f` runs on this thread when `timeout` is set and `objective` cannot handle timeouts by itself
+    watchdog: Option<Watchdog<T>>,
+
+    stats: EvalStats,
+
+    // the first error since the last `take_errors`, and the number of errors
+    first_error: Option<EvalError>,
+    error_count: usize,
+}
+
+struct Watchdog<T: Float> {
+    tx: mpsc::Sender<Vec<T>>,
+    rx: mpsc::Receiver<Result<T, EvalError>>,
+}
+
+impl<T: Float> Evaluator<T> {
+    pub fn new(objective: Objective, timeout: Option<Duration>, penalty_loss: T) -> Self {
+        Evaluator {
+            objective,
+            loss_function: None,
+            timeout,
+            penalty_loss,
+            watchdog: None,
+            stats: EvalStats::default(),
+            first_error: None,
+            error_count: 0,
+        }
+    }
+
+    pub fn eval(&mut self, parameters: &[T]) -> Option<T> {
+        let started_at = Instant::now();
+        let result = match self.timeout {
+            Some(timeout) if !self.objective.handles_timeout() => self.eval_with_watchdog(parameters, timeout),
+            timeout => {
+                let result = call_loss_function(&mut self.loss_function, &self.objective, parameters, timeout);
+
+                if result.is_err() {
+                    self.loss_function = None;
+                }
+
+                result
+            },
+        };
+
+        self.stats.evaluations += 1;
+        self.stats.eval_time += started_at.elapsed();
+
+        let loss = match result {
+            Ok(loss) => loss,
+            Err(e) => {
+                self.add_error(e);
+                self.penalty_loss
+            },
+        };
+
+        if loss.is_finite() {
+            Some(loss)
+        }
+
+        else {
+            self.add_error(EvalError::NonFinite {
+                loss: loss.to_f64(),
+                parameters: T::to_f64_slice(parameters).into_owned(),
+            });
+            None
+        }
+    }
+
+    fn add_error(&mut self, e: EvalError) {
+        match &e {
+            EvalError::Panicked(_) | EvalError::Failed(_) => { self.stats.panics += 1; },
+            EvalError::TimedOut(_) => { self.stats.timeouts += 1; },
+            EvalError::NonFinite { .. } => { self.stats.non_finite += 1; },
+        }
+
+        if self.first_error.is_none() {
+            self.first_error = Some(e);
+        }
+
+        self.error_count += 1;
+    }
+
+    fn eval_with_watchdog(&mut self, parameters: &[T], timeout: Duration) -> Result<T, EvalError> {
+        let objective = &self.objective;
+        let watchdog = self.watchdog.get_or_insert_with(|| Watchdog::spawn(objective.clone()));
+
+        if watchdog.tx.send(parameters.to_vec()).is_err() {
+            // the thread is dead, which is not supposed to happen
+            self.watchdog = None;
+            return Err(EvalError::Panicked(String::from("the evaluation thread is dead")));
+        }
+
+        match watchdog.rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(_) => {
+                // There's no way to kill a thread. The stuck thread is abandoned: it dies
+                // when `f` returns and it finds that nobody is listening.
+                self.watchdog = None;
+                Err(EvalError::TimedOut(timeout))
+            },
+        }
+    }
+
+    // None if the objective doesn't provide a gradient, or it fails
+    // it's not guarded by `timeout`, but a panic is caught
+    pub fn gradient(&mut self, parameters: &[T]) -> Option<Vec<T>> {
+        if self.loss_function.is_none() {
+            match self.objective.instantiate() {
+                Ok(loss_function) => { self.loss_function = Some(loss_function); },
+                Err(e) => {
+                    self.add_error(e);
+                    return None;
+                },
+            }
+        }
+
+        // it's instantiated above
+        let loss_function = self.loss_function.as_mut().unwrap();
+
+        match catch_panic(|| loss_function.gradient(parameters)) {
+            Ok(None) => None,
+            Ok(Some(Ok(gradient))) => Some(gradient),
+            Ok(Some(Err(e))) => {
+                self.add_error(e);
+                None
+            },
+            Err(msg) => {
+                self.loss_function = None;
+                self.add_error(EvalError::Panicked(msg));
+                None
+            },
+        }
+    }
+
+    // counters since the last call
+    pub fn take_stats(&mut self) -> EvalStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    // (the first error, the number of errors) since the last call
+    pub fn take_errors(&mut self) -> Option<(EvalError, usize)> {
+        let error_count = self.error_count;
+        self.error_count = 0;
+
+        self.first_error.take().map(|e| (e, error_count))
+    }
+}
+
+impl<T: Float> Watchdog<T> {
+    fn spawn(objective: Objective) -> Self {
+        let (tx, rx_params) = mpsc::channel::<Vec<T>>();
+        let (tx_result, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut loss_function = None;
+
+            while let Ok(parameters) = rx_params.recv() {
+                let result = call_loss_function(&mut loss_function, &objective, &parameters, None);
+
+                if result.is_err() {
+                    loss_function = None;
+                }
+
+                if tx_result.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Watchdog { tx, rx }
+    }
+}
+
+fn call_loss_function<T: Float>(
+    loss_function: &mut Option<LossFunction>,
+    objective: &Objective,
+    parameters: &[T],
+    timeout: Option<Duration>,
+) -> Result<T, EvalError> {
+    let loss_function = match loss_function {
+        Some(loss_function) => loss_function,
+        None => loss_function.insert(objective.instantiate()?),
+    };
+
+    match catch_panic(|| loss_function.call(parameters, timeout)) {
+        Ok(result) => result,
+        Err(msg) => Err(EvalError::Panicked(msg)),
+    }
+}
+
+fn catch_panic<R, F: FnOnce() -> R>(func: F) -> Result<R, String> {
+    panic::catch_unwind(AssertUnwindSafe(func)).map_err(|e| panic_message(&*e))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    }
+
+    else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    }
+
+    else {
+        String::from("unknown panic payload")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // panics if the first parameter is negative, and sleeps if it's bigger than 1
+    fn f(parameters: &[f32]) -> f32 {
+        if parameters[0] < 0.0 {
+            panic!("negative parameter");
+        }
+
+        if parameters[0] > 1.0 {
+            thread::sleep(Duration::from_millis(500));
+        }
+
+        parameters.iter().map(|p| p * p).sum()
+    }
+
+    #[test]
+    fn panics_become_penalties() {
+        let mut evaluator = Evaluator::new(Objective::Native(f), None, 100.0f32);
+
+        assert_eq!(evaluator.eval(&[-1.0, 0.0]), Some(100.0));
+
+        // the evaluator keeps working
+        assert_eq!(evaluator.eval(&[0.5, 0.5]), Some(0.5));
+
+        match evaluator.take_errors() {
+            Some((EvalError::Panicked(msg), 1)) => { assert_eq!(msg, "negative parameter"); },
+            _ => panic!("expected a single panic"),
+        }
+
+        let stats = evaluator.take_stats();
+        assert_eq!((stats.evaluations, stats.panics, stats.timeouts), (2, 1, 0));
+    }
+
+    #[test]
+    fn timeouts_spawn_a_fresh_watchdog() {
+        let timeout = Duration::from_millis(50);
+        let mut evaluator = Evaluator::new(Objective::Native(f), Some(timeout), 100.0f32);
+
+        assert_eq!(evaluator.eval(&[0.5]), Some(0.25));
+        assert!(evaluator.watchdog.is_some());
+
+        assert_eq!(evaluator.eval(&[2.0]), Some(100.0));
+        assert!(evaluator.watchdog.is_none());
+
+        // The stuck thread is still sleeping, so an answer within the timeout has to
+        // come from a new thread. A panic on the watchdog is caught there, too.
+        assert_eq!(evaluator.eval(&[0.5]), Some(0.25));
+        assert!(evaluator.watchdog.is_some());
+        assert_eq!(evaluator.eval(&[-1.0]), Some(100.0));
+        assert_eq!(evaluator.eval(&[0.5]), Some(0.25));
+
+        match evaluator.take_errors() {
+            Some((EvalError::TimedOut(t), 2)) => { assert_eq!(t, timeout); },
+            _ => panic!("expected a timeout, then a panic"),
+        }
+
+        let stats = evaluator.take_stats();
+        assert_eq!((stats.evaluations, stats.panics, stats.timeouts), (5, 1, 1));
+    }
+
+    #[test]
+    fn counters_reset_after_each_take() {
+        let mut evaluator = Evaluator::new(Objective::Native(|_| f32::NAN), None, 100.0f32);
+
+        assert_eq!(evaluator.eval(&[1.0]), None);
+        assert_eq!(evaluator.eval(&[2.0]), None);
+
+        match evaluator.take_errors() {
+            Some((EvalError::NonFinite { parameters, .. }, 2)) => { assert_eq!(parameters, vec![1.0]); },
+            _ => panic!("expected 2 non-finite losses"),
+        }
+
+        let stats = evaluator.take_stats();
+        assert_eq!((stats.evaluations, stats.non_finite), (2, 2));
+
+        assert!(evaluator.take_errors().is_none());
+        let stats = evaluator.take_stats();
+        assert_eq!((stats.evaluations, stats.non_finite, stats.eval_time), (0, 0, Duration::ZERO));
+
+        // the count starts again from the next error
+        evaluator.eval(&[3.0]);
+        assert!(matches!(evaluator.take_errors(), Some((EvalError::NonFinite { .. }, 1))));
+        assert_eq!(evaluator.take_stats().evaluations, 1);
+    }
+}
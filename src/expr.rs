@@ -0,0 +1,693 @@
+// A small language for objectives, so that quick experiments don't need a new `config::f`.
+//
+// example: `sum((x[i] - 1)^2) + 0.1 * x[0] * x[1]`
+//
+// - `x[<expr>]`: a parameter (0-based). The index has to be an integer in 0..n.
+// - `n`: the number of parameters
+// - `pi`, `e`: constants
+// - operators: `+`, `-`, `*`, `/`, `%`, `^` (right associative), unary `-`
+// - `sum(<expr>)`, `prod(<expr>)`: `i` goes 0..n
+// - `sum(<var>, <from>, <to>, <expr>)`, `prod(...)`: `<var>` goes `<from>..<to>` (`<to>` is exclusive)
+// - functions: sin, cos, tan, asin, acos, atan, sinh, cosh, tanh, exp, ln, log (= ln), log2, log10,
+//              sqrt, abs, floor, ceil, round, sign, pow(a, b), min(a, b, ...), max(a, b, ...)
+//
+// An expression is compiled once per process (see `compile_cached`) and shared by all the workers.
+// It's always evaluated in `f64`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub struct ExprError {
+    pub message: String,
+
+    // in bytes
+    pub position: usize,
+}
+
+impl ExprError {
+    pub fn render_error(&self, source: &str) -> String {
+        format!(
+            "{}\n{source}\n{}^",
+            self.message,
+            " ".repeat(source[..self.position.min(source.len())].chars().count()),
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Function {
+    Sin, Cos, Tan, Asin, Acos, Atan, Sinh, Cosh, Tanh,
+    Exp, Ln, Log2, Log10, Sqrt, Abs, Floor, Ceil, Round, Sign,
+    Pow, Min, Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        let f = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "ln" | "log" => Function::Ln,
+            "log2" => Function::Log2,
+            "log10" => Function::Log10,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "round" => Function::Round,
+            "sign" => Function::Sign,
+            "pow" => Function::Pow,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => { return None; },
+        };
+
+        Some(f)
+    }
+
+    // None: variadic (at least 1)
+    fn arity(&self) -> Option<usize> {
+        match self {
+            Function::Pow => Some(2),
+            Function::Min | Function::Max => None,
+            _ => Some(1),
+        }
+    }
+
+    fn call(&self, args: &[f64]) -> f64 {
+        match self {
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Tan => args[0].tan(),
+            Function::Asin => args[0].asin(),
+            Function::Acos => args[0].acos(),
+            Function::Atan => args[0].atan(),
+            Function::Sinh => args[0].sinh(),
+            Function::Cosh => args[0].cosh(),
+            Function::Tanh => args[0].tanh(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Log2 => args[0].log2(),
+            Function::Log10 => args[0].log10(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Abs => args[0].abs(),
+            Function::Floor => args[0].floor(),
+            Function::Ceil => args[0].ceil(),
+            Function::Round => args[0].round(),
+            Function::Sign => if args[0] == 0.0 { 0.0 } else { args[0].signum() },
+            Function::Pow => args[0].powf(args[1]),
+            Function::Min => args.iter().copied().fold(f64::INFINITY, f64::min),
+            Function::Max => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum BinaryOp {
+    Add, Sub, Mul, Div, Rem, Pow,
+}
+
+enum Node {
+    Number(f64),
+
+    // x[index]
+    Param {
+        index: Box<Node>,
+
+        // for error messages
+        position: usize,
+    },
+
+    // the value of a variable bound by `sum` or `prod`
+    Variable(usize),
+
+    // n
+    Dimension,
+
+    Neg(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+
+    // sum or prod
+    Reduce {
+        is_sum: bool,
+
+        // index of the variable
+        variable: usize,
+
+        // None: 0
+        from: Option<Box<Node>>,
+
+        // None: n
+        to: Option<Box<Node>>,
+
+        body: Box<Node>,
+    },
+}
+
+pub struct Expr {
+    root: Node,
+
+    // the number of variables bound by `sum` and `prod`
+    variable_count: usize,
+}
+
+impl Expr {
+    pub fn compile(source: &str) -> Result<Self, ExprError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            cursor: 0,
+            source_len: source.len(),
+            scopes: vec![],
+            variable_count: 0,
+        };
+        let root = parser.parse_expr()?;
+
+        if let Some((_, position)) = parser.tokens.get(parser.cursor) {
+            return Err(ExprError {
+                message: String::from("unexpected token"),
+                position: *position,
+            });
+        }
+
+        Ok(Expr {
+            root,
+            variable_count: parser.variable_count,
+        })
+    }
+
+    pub fn eval(&self, parameters: &[f64]) -> Result<f64, String> {
+        let mut variables = vec![0.0; self.variable_count];
+
+        eval_node(&self.root, parameters, &mut variables)
+    }
+}
+
+// It compiles each source only once per process.
+pub fn compile_cached(source: &str) -> Result<Arc<Expr>, ExprError> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Arc<Expr>>>> = OnceLock::new();
+
+    let mut cache = CACHE.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+
+    if let Some(expr) = cache.get(source) {
+        return Ok(expr.clone());
+    }
+
+    let expr = Arc::new(Expr::compile(source)?);
+    cache.insert(source.to_string(), expr.clone());
+
+    Ok(expr)
+}
+
+fn eval_node(node: &Node, parameters: &[f64], variables: &mut [f64]) -> Result<f64, String> {
+    let result = match node {
+        Node::Number(n) => *n,
+        Node::Param { index, position } => {
+            let index_f = eval_node(index, parameters, variables)?;
+
+            if index_f.fract() != 0.0 || index_f < 0.0 || index_f >= parameters.len() as f64 {
+                return Err(format!(
+                    "index {index_f} (at byte {position}) is not an integer in 0..{}",
+                    parameters.len(),
+                ));
+            }
+
+            parameters[index_f as usize]
+        },
+        Node::Variable(variable) => variables[*variable],
+        Node::Dimension => parameters.len() as f64,
+        Node::Neg(operand) => -eval_node(operand, parameters, variables)?,
+        Node::Binary(op, lhs, rhs) => {
+            let lhs = eval_node(lhs, parameters, variables)?;
+            let rhs = eval_node(rhs, parameters, variables)?;
+
+            match op {
+                BinaryOp::Add => lhs + rhs,
+                BinaryOp::Sub => lhs - rhs,
+                BinaryOp::Mul => lhs * rhs,
+                BinaryOp::Div => lhs / rhs,
+                BinaryOp::Rem => lhs % rhs,
+                BinaryOp::Pow => lhs.powf(rhs),
+            }
+        },
+        Node::Call(function, args) => {
+            let args = args.iter().map(
+                |arg| eval_node(arg, parameters, variables)
+            ).collect::<Result<Vec<_>, _>>()?;
+
+            function.call(&args)
+        },
+        Node::Reduce { is_sum, variable, from, to, body } => {
+            let from = match from {
+                Some(from) => eval_node(from, parameters, variables)?.round() as i64,
+                None => 0,
+            };
+            let to = match to {
+                Some(to) => eval_node(to, parameters, variables)?.round() as i64,
+                None => parameters.len() as i64,
+            };
+            let mut result = if *is_sum { 0.0 } else { 1.0 };
+
+            for value in from..to {
+                variables[*variable] = value as f64;
+                let curr = eval_node(body, parameters, variables)?;
+
+                if *is_sum {
+                    result += curr;
+                } else {
+                    result *= curr;
+                }
+            }
+
+            result
+        },
+    };
+
+    Ok(result)
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Punct(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let bytes = source.as_bytes();
+    let mut result = vec![];
+    let mut cursor = 0;
+
+    while cursor < bytes.len() {
+        let c = bytes[cursor];
+
+        if c.is_ascii_whitespace() {
+            cursor += 1;
+        }
+
+        else if c.is_ascii_digit() || c == b'.' {
+            let start = cursor;
+
+            while cursor < bytes.len() && (bytes[cursor].is_ascii_digit() || bytes[cursor] == b'.') {
+                cursor += 1;
+            }
+
+            // exponent: 1e-3
+            if cursor < bytes.len() && (bytes[cursor] == b'e' || bytes[cursor] == b'E') {
+                let mut end = cursor + 1;
+
+                if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+                    end += 1;
+                }
+
+                if end < bytes.len() && bytes[end].is_ascii_digit() {
+                    while end < bytes.len() && bytes[end].is_ascii_digit() {
+                        end += 1;
+                    }
+
+                    cursor = end;
+                }
+            }
+
+            match source[start..cursor].parse::<f64>() {
+                Ok(n) => { result.push((Token::Number(n), start)); },
+                Err(_) => {
+                    return Err(ExprError {
+                        message: format!("invalid number: `{}`", &source[start..cursor]),
+                        position: start,
+                    });
+                },
+            }
+        }
+
+        else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = cursor;
+
+            while cursor < bytes.len() && (bytes[cursor].is_ascii_alphanumeric() || bytes[cursor] == b'_') {
+                cursor += 1;
+            }
+
+            result.push((Token::Identifier(source[start..cursor].to_string()), start));
+        }
+
+        else if b"+-*/%^()[],".contains(&c) {
+            result.push((Token::Punct(c as char), cursor));
+            cursor += 1;
+        }
+
+        else {
+            return Err(ExprError {
+                message: format!("unexpected character: `{}`", source[cursor..].chars().next().unwrap()),
+                position: cursor,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    cursor: usize,
+    source_len: usize,
+
+    // (name, index) of variables bound by `sum` and `prod`
+    scopes: Vec<(String, usize)>,
+    variable_count: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.cursor).map(|(_, position)| *position).unwrap_or(self.source_len)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ExprError> {
+        Err(ExprError {
+            message: message.to_string(),
+            position: self.position(),
+        })
+    }
+
+    fn consume_punct(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.cursor += 1;
+            true
+        }
+
+        else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), ExprError> {
+        if self.consume_punct(c) {
+            Ok(())
+        }
+
+        else {
+            self.error(&format!("expected `{c}`"))
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn parse_expr(&mut self) -> Result<Node, ExprError> {
+        let mut lhs = self.parse_term()?;
+
+        loop {
+            let op = if self.consume_punct('+') {
+                BinaryOp::Add
+            } else if self.consume_punct('-') {
+                BinaryOp::Sub
+            } else {
+                break;
+            };
+
+            let rhs = self.parse_term()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    // term := unary (('*' | '/' | '%') unary)*
+    fn parse_term(&mut self) -> Result<Node, ExprError> {
+        let mut lhs = self.parse_unary()?;
+
+        loop {
+            let op = if self.consume_punct('*') {
+                BinaryOp::Mul
+            } else if self.consume_punct('/') {
+                BinaryOp::Div
+            } else if self.consume_punct('%') {
+                BinaryOp::Rem
+            } else {
+                break;
+            };
+
+            let rhs = self.parse_unary()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    // unary := '-' unary | power
+    fn parse_unary(&mut self) -> Result<Node, ExprError> {
+        if self.consume_punct('-') {
+            Ok(Node::Neg(Box::new(self.parse_unary()?)))
+        }
+
+        else {
+            self.parse_power()
+        }
+    }
+
+    // power := primary ('^' unary)?
+    // `-x^2` is `-(x^2)` and `2^-1` is `2^(-1)`
+    fn parse_power(&mut self) -> Result<Node, ExprError> {
+        let base = self.parse_primary()?;
+
+        if self.consume_punct('^') {
+            let exponent = self.parse_unary()?;
+            Ok(Node::Binary(BinaryOp::Pow, Box::new(base), Box::new(exponent)))
+        }
+
+        else {
+            Ok(base)
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, ExprError> {
+        let position = self.position();
+
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.cursor += 1;
+                Ok(Node::Number(n))
+            },
+            Some(Token::Punct('(')) => {
+                self.cursor += 1;
+                let result = self.parse_expr()?;
+                self.expect_punct(')')?;
+
+                Ok(result)
+            },
+            Some(Token::Identifier(name)) => {
+                self.cursor += 1;
+
+                if self.peek() == Some(&Token::Punct('(')) {
+                    self.cursor += 1;
+                    self.parse_call(&name, position)
+                }
+
+                else if name == "x" {
+                    self.expect_punct('[')?;
+                    let index = self.parse_expr()?;
+                    self.expect_punct(']')?;
+
+                    Ok(Node::Param { index: Box::new(index), position })
+                }
+
+                else if let Some((_, variable)) = self.scopes.iter().rev().find(|(n, _)| *n == name) {
+                    Ok(Node::Variable(*variable))
+                }
+
+                else {
+                    match name.as_str() {
+                        "n" => Ok(Node::Dimension),
+                        "pi" => Ok(Node::Number(std::f64::consts::PI)),
+                        "e" => Ok(Node::Number(std::f64::consts::E)),
+                        _ => Err(ExprError {
+                            message: format!("unknown variable: `{name}`"),
+                            position,
+                        }),
+                    }
+                }
+            },
+            Some(_) => self.error("expected an expression"),
+            None => self.error("unexpected end of expression"),
+        }
+    }
+
+    // `(` is already consumed
+    fn parse_call(&mut self, name: &str, position: usize) -> Result<Node, ExprError> {
+        if name == "sum" || name == "prod" {
+            return self.parse_reduce(name == "sum");
+        }
+
+        let function = match Function::from_name(name) {
+            Some(function) => function,
+            None => {
+                return Err(ExprError {
+                    message: format!("unknown function: `{name}`"),
+                    position,
+                });
+            },
+        };
+
+        let mut args = vec![self.parse_expr()?];
+
+        while self.consume_punct(',') {
+            args.push(self.parse_expr()?);
+        }
+
+        self.expect_punct(')')?;
+
+        match function.arity() {
+            Some(arity) if arity != args.len() => Err(ExprError {
+                message: format!("`{name}` takes {arity} argument(s), but got {}", args.len()),
+                position,
+            }),
+            _ => Ok(Node::Call(function, args)),
+        }
+    }
+
+    // sum(body) or sum(var, from, to, body)
+    fn parse_reduce(&mut self, is_sum: bool) -> Result<Node, ExprError> {
+        let variable = self.variable_count;
+        self.variable_count += 1;
+
+        // sum(var, from, to, body)
+        let explicit_name = match (self.tokens.get(self.cursor), self.tokens.get(self.cursor + 1)) {
+            (Some((Token::Identifier(name), _)), Some((Token::Punct(','), _))) => Some(name.clone()),
+            _ => None,
+        };
+
+        let (name, from, to) = match explicit_name {
+            Some(name) => {
+                self.cursor += 2;
+                let from = self.parse_expr()?;
+                self.expect_punct(',')?;
+                let to = self.parse_expr()?;
+                self.expect_punct(',')?;
+
+                (name, Some(Box::new(from)), Some(Box::new(to)))
+            },
+            None => (String::from("i"), None, None),
+        };
+
+        self.scopes.push((name, variable));
+        let body = self.parse_expr()?;
+        self.scopes.pop();
+        self.expect_punct(')')?;
+
+        Ok(Node::Reduce {
+            is_sum,
+            variable,
+            from,
+            to,
+            body: Box::new(body),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, parameters: &[f64]) -> f64 {
+        Expr::compile(source).map_err(|e| e.render_error(source)).unwrap().eval(parameters).unwrap()
+    }
+
+    // (message, position)
+    fn compile_error(source: &str) -> (String, usize) {
+        match Expr::compile(source) {
+            Ok(_) => panic!("`{source}` has to be an error"),
+            Err(e) => (e.message, e.position),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("-x[0]^2", &[3.0]), -9.0);
+        assert_eq!(eval("-2^2", &[]), -4.0);
+        assert_eq!(eval("2^3^2", &[]), 512.0);
+        assert_eq!(eval("2^-1", &[]), 0.5);
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2", &[]), 5.0);
+        assert_eq!(eval("(1 + 2) * 3", &[]), 9.0);
+        assert_eq!(eval("10 - 4 - 3", &[]), 3.0);
+        assert_eq!(eval("7 % 4 * 2", &[]), 6.0);
+        assert_eq!(eval("2 * x[1]^2", &[0.0, 3.0]), 18.0);
+    }
+
+    #[test]
+    fn reduce() {
+        let parameters = [1.0, 2.0, 3.0];
+
+        assert_eq!(eval("sum(x[i])", &parameters), 6.0);
+        assert_eq!(eval("prod(x[i] + 1)", &parameters), 24.0);
+        assert_eq!(eval("sum(j, 1, n, x[j])", &parameters), 5.0);
+        assert_eq!(eval("prod(j, 0, 0, x[j])", &parameters), 1.0);
+
+        // the inner `i` shadows the outer one
+        assert_eq!(eval("sum(sum(x[i]))", &parameters), 18.0);
+        assert_eq!(eval("sum(x[i] * sum(j, 0, i + 1, 1))", &parameters), 1.0 + 4.0 + 9.0);
+
+        // 0! + 1! + 2! + 3!
+        assert_eq!(eval("sum(j, 0, 4, prod(k, 1, j + 1, k))", &parameters), 10.0);
+
+        // the body is the whole expression up to `)`
+        assert_eq!(eval("sum(x[i] + 1) * 2", &parameters), 18.0);
+
+        // a variable is only visible in its body
+        assert_eq!(compile_error("sum(x[i]) + i"), (String::from("unknown variable: `i`"), 12));
+        assert_eq!(compile_error("sum(j, 0, 2, j) + j"), (String::from("unknown variable: `j`"), 18));
+    }
+
+    #[test]
+    fn hand_computed_values() {
+        let parameters = [0.5, -1.0, 2.0];
+
+        assert_eq!(eval("sum((x[i] - 1)^2) + 0.1 * x[0] * x[1]", &parameters), 0.25 + 4.0 + 1.0 - 0.05);
+        assert_eq!(eval("max(x[0], x[1], x[2]) - min(x[0], x[1])", &parameters), 3.0);
+        assert_eq!(eval("pow(x[2], 3) + abs(x[1]) + sign(x[1]) + sign(0)", &parameters), 8.0);
+        assert_eq!(eval("sqrt(16) + exp(0) + ln(e) + log10(1000) + log2(8)", &parameters), 12.0);
+        assert_eq!(eval("floor(2.7) + ceil(2.1) + round(2.5)", &parameters), 8.0);
+        assert_eq!(eval("1.5e2 + 2E-1 + .5", &parameters), 150.7);
+        assert_eq!(eval("n", &parameters), 3.0);
+        assert!((eval("sin(pi / 2) + cos(pi)", &parameters)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let expr = Expr::compile("x[3]").map_err(|e| e.render_error("x[3]")).unwrap();
+        assert!(expr.eval(&[1.0, 2.0, 3.0]).is_err());
+        assert_eq!(expr.eval(&[1.0, 2.0, 3.0, 4.0]).ok(), Some(4.0));
+
+        for source in ["x[-1]", "x[0.5]", "x[n]", "sum(x[i + 1])"] {
+            let expr = Expr::compile(source).map_err(|e| e.render_error(source)).unwrap();
+            assert!(expr.eval(&[1.0, 2.0, 3.0]).is_err(), "`{source}` has to be out of range");
+        }
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(compile_error("1 + * 2"), (String::from("expected an expression"), 4));
+        assert_eq!(compile_error("1 + 2)"), (String::from("unexpected token"), 5));
+        assert_eq!(compile_error("(1 + 2"), (String::from("expected `)`"), 6));
+        assert_eq!(compile_error("x[0"), (String::from("expected `]`"), 3));
+        assert_eq!(compile_error("x + 1"), (String::from("expected `[`"), 2));
+        assert_eq!(compile_error("1 + $"), (String::from("unexpected character: `$`"), 4));
+        assert_eq!(compile_error("2 * foo(1)"), (String::from("unknown function: `foo`"), 4));
+        assert_eq!(compile_error("1 + sin(1, 2)"), (String::from("`sin` takes 1 argument(s), but got 2"), 4));
+        assert_eq!(compile_error("1 +"), (String::from("unexpected end of expression"), 3));
+        assert_eq!(compile_error("1..2"), (String::from("invalid number: `1..2`"), 0));
+        assert_eq!(compile_error("sum(j, 0, 2 x[j])"), (String::from("expected `,`"), 12));
+
+        let source = "1 + * 2";
+        let e = Expr::compile(source).err().unwrap();
+        assert_eq!(e.render_error(source), "expected an expression\n1 + * 2\n    ^");
+    }
+}
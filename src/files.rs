@@ -231,6 +231,17 @@ pub fn remove_dir_all(path: &str) -> Result<(), FileError> {
     fs::remove_dir_all(path).map_err(|e| FileError::from_std(e, path))
 }
 
+// it replaces `to` if it exists
+pub fn rename(from: &str, to: &str) -> Result<(), FileError> {
+    fs::rename(from, to).map_err(|e| FileError::from_std(e, from))
+}
+
+// a path in the temporary directory, unique to this process, so that tests running in parallel don't share files
+#[cfg(test)]
+pub fn temp_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("nonlinear_opt_{}_{name}", std::process::id())).to_str().unwrap().to_string()
+}
+
 #[derive(Clone,  PartialEq)]
 pub struct FileError {
     pub kind: FileErrorKind,
@@ -267,7 +278,7 @@ impl FileError {
     }
 
     pub fn render_error(&self) -> String {
-        let path = self.given_path.as_ref().map(|p| p.to_string()).unwrap_or(String::new());
+        let path = self.given_path.as_ref().map(|p| p.to_string()).unwrap_or_default();
 
         match &self.kind {
             FileErrorKind::FileNotFound => format!(
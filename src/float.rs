@@ -0,0 +1,119 @@
+// The optimizer works with either `f32` or `f64`. The objective decides which one (see `Objective::precision`),
+// so one build can optimize objectives of both types.
+//
+// Values that the user configures (step sizes, losses in `Config`, `Stats`, ...) are always `f64`.
+
+use rand::Rng;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    F32,
+    F64,
+}
+
+impl Precision {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Precision::F32 => "f32",
+            Precision::F64 => "f64",
+        }
+    }
+}
+
+pub trait Float:
+    Copy
+    + Debug
+    + Display
+    + PartialOrd
+    + Send
+    + Sync
+    + Sum
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + 'static
+{
+    const PRECISION: Precision;
+    const ZERO: Self;
+    const ONE: Self;
+    const INFINITY: Self;
+
+    // size of `to_le_bytes`
+    const BYTES: usize;
+
+    fn from_f64(n: f64) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn sqrt(self) -> Self;
+    fn is_finite(self) -> bool;
+    fn total_cmp(&self, other: &Self) -> Ordering;
+
+    // uniform, 0 ~ 1
+    fn random<R: Rng>(rng: &mut R) -> Self;
+
+    fn write_le_bytes(self, buffer: &mut Vec<u8>);
+
+    // `bytes.len()` has to be `BYTES`
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+
+    // no copy if `Self` is already the target type
+    fn to_f32_slice(v: &[Self]) -> Cow<'_, [f32]>;
+    fn to_f64_slice(v: &[Self]) -> Cow<'_, [f64]>;
+}
+
+macro_rules! impl_float {
+    ($t:ty, $precision:expr, $f32_slice:ident, $f64_slice:ident) => {
+        impl Float for $t {
+            const PRECISION: Precision = $precision;
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+            const INFINITY: Self = <$t>::INFINITY;
+            const BYTES: usize = std::mem::size_of::<$t>();
+
+            fn from_f64(n: f64) -> Self { n as $t }
+            fn to_f64(self) -> f64 { self as f64 }
+
+            fn sqrt(self) -> Self { <$t>::sqrt(self) }
+            fn is_finite(self) -> bool { <$t>::is_finite(self) }
+            fn total_cmp(&self, other: &Self) -> Ordering { <$t>::total_cmp(self, other) }
+
+            fn random<R: Rng>(rng: &mut R) -> Self { rng.gen::<$t>() }
+
+            fn write_le_bytes(self, buffer: &mut Vec<u8>) {
+                buffer.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn from_le_bytes(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            fn to_f32_slice(v: &[Self]) -> Cow<'_, [f32]> { $f32_slice(v) }
+            fn to_f64_slice(v: &[Self]) -> Cow<'_, [f64]> { $f64_slice(v) }
+        }
+    };
+}
+
+impl_float!(f32, Precision::F32, borrow, f32_to_f64);
+impl_float!(f64, Precision::F64, f64_to_f32, borrow);
+
+fn borrow<T: Clone>(v: &[T]) -> Cow<'_, [T]> {
+    Cow::Borrowed(v)
+}
+
+fn f32_to_f64(v: &[f32]) -> Cow<'_, [f64]> {
+    Cow::Owned(v.iter().map(|n| *n as f64).collect())
+}
+
+fn f64_to_f32(v: &[f64]) -> Cow<'_, [f32]> {
+    Cow::Owned(v.iter().map(|n| *n as f32).collect())
+}
@@ -0,0 +1,101 @@
+// `nonlinear_opt generate <parameters> <prompt> [<length> [<temperature>]]`: writes text with a model of `samples/text.rs`.
+//
+// `<parameters>` is either a checkpoint (`Config.checkpoint`), whose best state is used, or a file of
+// `Config.initial_points`, whose first vector is used. The model is `samples::text::SPEC`, so it has to be
+// the same one that the parameters are trained with.
+//
+// It prints the prompt and then `<length>` (default: 256) generated bytes. `<temperature>` (default: 0)
+// is explained at `samples::text::generate`.
+
+use crate::checkpoint::load_checkpoint;
+use crate::files::read_bytes;
+use crate::points::load_points;
+use crate::samples::text::{self, SPEC};
+use rand::thread_rng;
+use std::io::Write;
+
+const DEFAULT_LENGTH: usize = 256;
+
+// `args` are the arguments after `generate`
+pub fn run_generate(args: &[String]) -> Result<(), String> {
+    let [path, prompt, rest @ ..] = args else {
+        return Err(String::from("`generate` needs <parameters> and <prompt>"));
+    };
+    let length = match rest.first() {
+        Some(length) => length.parse::<usize>().map_err(|_| format!("`{length}` is not a length"))?,
+        None => DEFAULT_LENGTH,
+    };
+    let temperature = match rest.get(1) {
+        Some(t) => match t.parse::<f32>() {
+            Ok(t) if t.is_finite() && t >= 0.0 => t,
+            _ => { return Err(format!("`{t}` is not a temperature")); },
+        },
+        None => 0.0,
+    };
+
+    if prompt.is_empty() {
+        return Err(String::from("the prompt has to have at least one byte"));
+    }
+
+    let parameters = load_parameters(path, SPEC.param_size())?;
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(prompt.as_bytes()).map_err(|e| format!("cannot write to stdout: {e}"))?;
+
+    text::generate(
+        &SPEC,
+        &parameters,
+        prompt.as_bytes(),
+        length,
+        temperature,
+        &mut thread_rng(),
+        |byte| {
+            // `println!` panics if stdout is closed, but this one just stops writing
+            let _ = stdout.write_all(&[byte]).and_then(|_| stdout.flush());
+        },
+    );
+
+    let _ = writeln!(stdout);
+
+    Ok(())
+}
+
+// `save_checkpoint` always starts the file with `dimension`
+fn load_parameters(path: &str, dimension: usize) -> Result<Vec<f32>, String> {
+    let is_checkpoint = read_bytes(path).map_err(|e| e.render_error())?.starts_with(b"dimension");
+
+    let parameters = if is_checkpoint {
+        load_checkpoint(path, dimension).map_err(|e| e.render_error())?.into_iter().min_by(
+            |a, b| a.loss.total_cmp(&b.loss)
+        ).unwrap().parameters
+    } else {
+        load_points(path, dimension).map_err(|e| e.render_error())?.swap_remove(0)
+    };
+
+    Ok(parameters.into_iter().map(|p| p as f32).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::{remove_file, temp_path, write_string, WriteMode};
+
+    #[test]
+    fn parameter_files() {
+        let checkpoint = temp_path("generate.checkpoint");
+        write_string(
+            &checkpoint,
+            "dimension 2\n\nstate\nloss 3\nparameters 1 1\n\nstate\nloss 0.5\nparameters 2 -2\n",
+            WriteMode::CreateOrTruncate,
+        ).unwrap();
+        assert_eq!(load_parameters(&checkpoint, 2).unwrap(), vec![2.0, -2.0]);
+        assert!(load_parameters(&checkpoint, 3).is_err());
+        remove_file(&checkpoint).unwrap();
+
+        let csv = temp_path("generate.csv");
+        write_string(&csv, "0.5, 0.25\n1, 1\n", WriteMode::CreateOrTruncate).unwrap();
+        assert_eq!(load_parameters(&csv, 2).unwrap(), vec![0.5, 0.25]);
+        remove_file(&csv).unwrap();
+
+        assert!(load_parameters(&temp_path("generate.missing"), 2).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+// Named slices of the parameter vector with their own step controls (`Config.parameter_groups`).
+//
+// For example, the weights, the biases and the output layer of an LSTM live in one vector,
+// but they want different step sizes. The parameters that are not in any group use
+// `Config.initial_step_size` and `Config.step_moment`.
+//
+// A group changes how a worker draws steps (see `multi::event_loop`): each group gets
+// its own random step, whose l2 norm is the group's `step_size` in the first step, and
+// the l2 norm of the group's part of the previous step afterwards. A frozen group never moves.
+
+use crate::float::Float;
+use crate::utils::get_l2_norm;
+use std::ops::Range;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterGroup {
+    pub name: String,
+    pub range: Range<usize>,
+
+    // None: `Config.initial_step_size`
+    pub step_size: Option<f64>,
+
+    // None: `Config.step_moment`
+    pub step_moment: Option<f64>,
+    pub frozen: bool,
+}
+
+impl ParameterGroup {
+    // no step controls of its own; `config.rs` sets them with the struct update syntax
+    pub fn new(name: &str, range: Range<usize>) -> Self {
+        ParameterGroup {
+            name: name.to_string(),
+            range,
+            step_size: None,
+            step_moment: None,
+            frozen: false,
+        }
+    }
+}
+
+// It fails if a group is empty, out of `0..dimension`, or overlaps another group.
+pub fn validate_groups(groups: &[ParameterGroup], dimension: usize) -> Result<(), String> {
+    for (index, group) in groups.iter().enumerate() {
+        if group.range.is_empty() || group.range.end > dimension {
+            return Err(format!(
+                "parameter group `{}` ({:?}) has to be a non-empty range in 0..{dimension}",
+                group.name, group.range,
+            ));
+        }
+
+        if let Some(other) = groups[..index].iter().find(
+            |other| other.range.start < group.range.end && group.range.start < other.range.end
+        ) {
+            return Err(format!("parameter groups `{}` and `{}` overlap", other.name, group.name));
+        }
+
+        if group.step_size.map(|s| !s.is_finite() || s <= 0.0).unwrap_or(false) {
+            return Err(format!("the step_size of parameter group `{}` has to be positive", group.name));
+        }
+
+        if group.step_moment.map(|m| !(0.0..=1.0).contains(&m)).unwrap_or(false) {
+            return Err(format!("the step_moment of parameter group `{}` has to be 0 ~ 1", group.name));
+        }
+    }
+
+    Ok(())
+}
+
+// A slice of the parameters and its step controls. The segments of a vector cover every parameter.
+pub struct Segment<T: Float> {
+    pub range: Range<usize>,
+    pub step_size: T,
+    pub step_moment: T,
+    pub frozen: bool,
+}
+
+// The groups, and the gaps between them with the default controls, in order.
+pub fn segments<T: Float>(groups: &[ParameterGroup], dimension: usize, step_size: T, step_moment: T) -> Vec<Segment<T>> {
+    let mut groups = groups.iter().collect::<Vec<_>>();
+    groups.sort_by_key(|group| group.range.start);
+
+    let mut result = vec![];
+    let mut cursor = 0;
+
+    for group in groups.into_iter() {
+        if cursor < group.range.start {
+            result.push(Segment { range: cursor..group.range.start, step_size, step_moment, frozen: false });
+        }
+
+        result.push(Segment {
+            range: group.range.clone(),
+            step_size: group.step_size.map(T::from_f64).unwrap_or(step_size),
+            step_moment: group.step_moment.map(T::from_f64).unwrap_or(step_moment),
+            frozen: group.frozen,
+        });
+        cursor = group.range.end;
+    }
+
+    if cursor < dimension {
+        result.push(Segment { range: cursor..dimension, step_size, step_moment, frozen: false });
+    }
+
+    result
+}
+
+// a line of `State::pretty_print`
+pub fn pretty_print_group<T: Float>(group: &ParameterGroup, parameters: &[T], prev_step: Option<&[T]>) -> String {
+    let step_l2_norm = prev_step.map(|step| get_l2_norm(&step[group.range.clone()]));
+
+    format!(
+        "{} ({}..{}{}): l2_norm: {}, step l2_norm: {}",
+        group.name,
+        group.range.start,
+        group.range.end,
+        if group.frozen { ", frozen" } else { "" },
+        get_l2_norm(&parameters[group.range.clone()]),
+        step_l2_norm.map(|n| format!("{n}")).unwrap_or_else(|| String::from("None")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_cover_every_parameter() {
+        let mut bias = ParameterGroup::new("bias", 6..8);
+        bias.frozen = true;
+        let mut weight = ParameterGroup::new("weight", 2..6);
+        weight.step_size = Some(0.1);
+
+        let segments = segments(&[bias, weight], 10, 1.0f64, 0.5);
+
+        assert_eq!(segments.iter().map(|s| s.range.clone()).collect::<Vec<_>>(), vec![0..2, 2..6, 6..8, 8..10]);
+        assert_eq!(segments.iter().map(|s| s.step_size).collect::<Vec<_>>(), vec![1.0, 0.1, 1.0, 1.0]);
+        assert_eq!(segments.iter().map(|s| s.frozen).collect::<Vec<_>>(), vec![false, false, true, false]);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(validate_groups(&[ParameterGroup::new("a", 0..4), ParameterGroup::new("b", 4..8)], 8).is_ok());
+        assert!(validate_groups(&[ParameterGroup::new("a", 0..5), ParameterGroup::new("b", 4..8)], 8).is_err());
+        assert!(validate_groups(&[ParameterGroup::new("a", 0..9)], 8).is_err());
+        assert!(validate_groups(&[ParameterGroup::new("a", 3..3)], 8).is_err());
+    }
+}
@@ -0,0 +1,661 @@
+use crate::checkpoint::{load_checkpoint, save_checkpoint};
+use crate::config::{self, Config};
+use crate::files::exists;
+use crate::float::{Float, Precision};
+use crate::groups::validate_groups;
+use crate::log::{initialize_log_file, write_log};
+use crate::multi::{
+    distribute_messages,
+    init_channels,
+    Channel,
+    MessageFromMain,
+    MessageToMain,
+    WorkerConfig,
+};
+use crate::net;
+use crate::objective::Objective;
+use crate::points::load_points;
+use crate::sampling::{InitStrategy, Partition};
+use crate::scaling::Scales;
+use crate::state::State;
+use crate::stats::Stats;
+use crate::utils::{add_params, get_distance_of_params};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how long the master sleeps when no worker has sent anything
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// how often `config::visualizer` is called
+const VISUALIZE_INTERVAL: Duration = Duration::from_millis(800);
+
+// how often the states are written to `Config.checkpoint` (they're also written at the end)
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct RunSummary {
+    // converted to `f64`, whatever the precision of the objective is
+    pub best_params: Vec<f64>,
+    pub best_loss: f64,
+    pub evaluations: usize,
+
+    // None if `target_loss` is None or not reached
+    pub evaluations_to_target: Option<usize>,
+    pub elapsed: Duration,
+}
+
+// It runs until the budget is exhausted or the target loss is reached.
+// If neither `max_evaluations` nor `target_loss` is set, it never returns.
+pub fn run(config: Config) -> RunSummary {
+    match config.objective.precision() {
+        Precision::F32 => run_with::<f32>(config),
+        Precision::F64 => run_with::<f64>(config),
+    }
+}
+
+fn run_with<T: Float>(config: Config) -> RunSummary {
+    let worker_config = WorkerConfig::from_config(&config);
+    let Config {
+        objective,
+        num_workers,
+        iter_per_worker,
+        initial_l2_norm,
+        initial_step_size,
+        step_moment,
+        visualize,
+        write_logs_to,
+        remove_existing_log_file,
+        max_evaluations,
+        target_loss,
+        listen_for_workers,
+        dimension,
+        checkpoint,
+        resume_from_checkpoint,
+        initial_points,
+        mix_with_random_params,
+        init_strategy,
+        seed,
+        scaling,
+        parameter_groups,
+        subspace,
+        ..
+    } = config;
+
+    if let Some(path) = &write_logs_to {
+        initialize_log_file(path, remove_existing_log_file).unwrap();
+    }
+
+    write_log(
+        write_logs_to.clone(),
+        "master",
+        &format!("hello from master (precision: {})", T::PRECISION.name()),
+    );
+
+    if num_workers < 1 {
+        abort(&write_logs_to, "num_workers has to be at least 1! aborting...");
+    }
+
+    if !(0.0..=1.0).contains(&step_moment) {
+        abort(&write_logs_to, "step_moment has to be 0 ~ 1");
+    }
+
+    let dimension = match resolve_dimension(&objective, dimension) {
+        Ok(dimension) => dimension,
+        Err(e) => { abort(&write_logs_to, &e); },
+    };
+
+    match init_strategy {
+        InitStrategy::Uniform { lower, upper }
+        | InitStrategy::LatinHypercube { lower, upper }
+        | InitStrategy::Halton { lower, upper } if lower >= upper => {
+            abort(&write_logs_to, "the lower bound of init_strategy has to be less than the upper bound");
+        },
+        InitStrategy::Gaussian { scale } if scale <= 0.0 => {
+            abort(&write_logs_to, "the scale of init_strategy has to be positive");
+        },
+        _ => {},
+    }
+
+    if let Err(e) = scaling.validate(dimension) {
+        abort(&write_logs_to, &e);
+    }
+
+    let mut scales = Scales::new(scaling);
+
+    if let Err(e) = validate_groups(&parameter_groups, dimension) {
+        abort(&write_logs_to, &e);
+    }
+
+    if let Err(e) = subspace.validate(dimension) {
+        abort(&write_logs_to, &e);
+    }
+
+    // the states of the previous run, if any
+    let resumed_states = match &checkpoint {
+        Some(path) if resume_from_checkpoint && exists(path) => match load_checkpoint(path, dimension) {
+            Ok(states) => Some(states),
+            Err(e) => {
+                abort(&write_logs_to, &format!("cannot resume from the checkpoint: {}", e.render_error()));
+            },
+        },
+        _ => None,
+    };
+
+    // a checkpoint has priority over the initial points
+    let initial_points = match &initial_points {
+        Some(path) if resumed_states.is_none() => match load_points(path, dimension) {
+            Ok(points) => Some(points),
+            Err(e) => {
+                abort(&write_logs_to, &format!("cannot load the initial points: {}", e.render_error()));
+            },
+        },
+        _ => None,
+    };
+
+    let initial_l2_norm = T::from_f64(initial_l2_norm);
+    let initial_step_size = T::from_f64(initial_step_size);
+    let step_moment = T::from_f64(step_moment);
+
+    let channels: Vec<Channel<T>> = match &listen_for_workers {
+        Some(address) => match net::init_remote_channels(address, num_workers, dimension, &write_logs_to) {
+            Ok(channels) => channels,
+            Err(e) => {
+                abort(&write_logs_to, &format!("cannot listen for remote workers at {address}: {e}"));
+            },
+        },
+        None => init_channels(
+            num_workers,
+            &worker_config,
+        ),
+    };
+
+    let mut stats = Stats::new(num_workers, max_evaluations, target_loss);
+
+    let mut states = match resumed_states {
+        Some(resumed_states) => {
+            write_log(
+                write_logs_to.clone(),
+                "master",
+                &format!("resumed {} states from the checkpoint", resumed_states.len()),
+            );
+
+            let states = resumed_states.into_iter().enumerate().map(
+                |(id, state)| state.into_state::<T>(id)
+            ).collect::<Vec<_>>();
+
+            for state in states.iter() {
+                stats.update_best_loss(state.loss.to_f64());
+            }
+
+            states
+        },
+        None => {
+            let mut states = match initial_points {
+                Some(points) => evaluate_initial_points(&channels, &mut stats, points, &write_logs_to),
+                None => vec![],
+            };
+
+            if states.is_empty() || mix_with_random_params {
+                // the workers share this seed, so it doesn't have to be random
+                let partition_seed = seed.unwrap_or(0);
+                let messages = (0..channels.len()).map(
+                    |index| MessageFromMain::TryRandomParams {
+                        param_l2_norm: initial_l2_norm,
+                        dimension,
+                        count: iter_per_worker,
+                        strategy: init_strategy.clone(),
+                        partition: Partition { index, total: channels.len(), seed: partition_seed },
+                    }
+                ).collect();
+
+                states.extend(run_random_phase(&channels, &mut stats, messages, &write_logs_to));
+            }
+
+            for (id, state) in states.iter_mut().enumerate() {
+                state.id = id;
+            }
+
+            states
+        },
+    };
+
+    // `bases[worker_index][state_id]`: (version, curr_params) of the `TryWithGradient` that the worker is working on
+    // a result is a delta against its base
+    let mut bases = vec![vec![]; channels.len()];
+    let mut next_version = 0;
+
+    for (worker_index, channel) in channels.iter().enumerate() {
+        for state in states.iter() {
+            bases[worker_index].push((next_version, state.parameters.clone()));
+            channel.send(MessageFromMain::TryWithGradient {
+                state_id: state.id,
+                version: next_version,
+                curr_params: state.parameters.clone(),
+                prev_step: state.prev_step.clone(),
+                step_size: initial_step_size,
+                step_moment,
+                count: iter_per_worker,
+                scales: scales.get(),
+            }).unwrap();
+            next_version += 1;
+        }
+    }
+
+    let mut last_stats_log = Instant::now();
+    let mut last_visualized = Instant::now();
+    let mut last_checkpoint = Instant::now();
+
+    while !stats.budget_exhausted() && !stats.target_reached() {
+        let mut got_message = false;
+
+        for (worker_index, channel) in channels.iter().enumerate() {
+            // it stops right after the message that exhausts the budget, so a run with 1 worker
+            // doesn't depend on how many messages arrived at once
+            while !stats.budget_exhausted() && !stats.target_reached() {
+                let Ok(msg) = channel.try_recv() else { break; };
+                got_message = true;
+
+                // (state_id, curr_params, prev_step) of the next `TryWithGradient` to this worker
+                let next_try = match msg {
+                    MessageToMain::WithGradientResult {
+                        state_id,
+                        version,
+                        best_loss,
+                        step,
+                        stats: worker_stats,
+                    } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: with_gradient_result(state: {state_id}, loss: {best_loss:.4}, evaluations: {})", worker_stats.evaluations),
+                        );
+
+                        stats.add_worker_stats(worker_index, &worker_stats);
+                        states[state_id].evaluations += worker_stats.evaluations;
+
+                        let best_params = apply_step(&bases[worker_index][state_id], version, &step);
+
+                        // workers never send non-finite losses, but a state's loss can be infinity
+                        // if every candidate of the random phase was rejected
+                        if best_loss.is_finite() && best_loss < states[state_id].loss {
+                            scales.observe(&step);
+                            states[state_id].update_best_loss(
+                                best_params,
+                                best_loss,
+                                step,
+                            );
+                            stats.add_acceptance(worker_index);
+                            stats.update_best_loss(best_loss.to_f64());
+
+                            Some((state_id, states[state_id].parameters.clone(), states[state_id].prev_step.clone()))
+                        }
+
+                        // the worker continues from its own best
+                        else {
+                            states[state_id].failed_turns += 1;
+                            stats.add_failure(worker_index);
+
+                            Some((state_id, best_params.into(), Some(step.into())))
+                        }
+                    },
+                    MessageToMain::WithGradientResultFailure { state_id, stats: worker_stats } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: with_gradient_result_failure(state: {state_id}, evaluations: {})", worker_stats.evaluations),
+                        );
+
+                        stats.add_worker_stats(worker_index, &worker_stats);
+                        stats.add_failure(worker_index);
+                        states[state_id].evaluations += worker_stats.evaluations;
+                        states[state_id].failed_turns += 1;
+
+                        Some((state_id, states[state_id].parameters.clone(), states[state_id].prev_step.clone()))
+                    },
+                    MessageToMain::SparseResult {
+                        state_id,
+                        version,
+                        indices,
+                        delta,
+                        best_loss,
+                        stats: worker_stats,
+                    } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: sparse_result(state: {state_id}, coordinates: {}, loss: {best_loss:.4}, evaluations: {})", indices.len(), worker_stats.evaluations),
+                        );
+
+                        stats.add_worker_stats(worker_index, &worker_stats);
+                        let (base_version, base) = &bases[worker_index][state_id];
+                        assert_eq!(version, *base_version, "a result has to answer the last `TryWithGradient`");
+
+                        let state = &mut states[state_id];
+                        state.evaluations += worker_stats.evaluations;
+
+                        // the base may be older than the state, but the loss is the loss of base + delta
+                        if best_loss.is_finite() && best_loss < state.loss {
+                            state.apply_delta(base, &indices, &delta, best_loss);
+                            stats.add_acceptance(worker_index);
+                            stats.update_best_loss(best_loss.to_f64());
+                            scales.observe_sparse(dimension, &indices, &delta);
+                        }
+
+                        else {
+                            state.failed_turns += 1;
+                            stats.add_failure(worker_index);
+                        }
+
+                        Some((state_id, state.parameters.clone(), state.prev_step.clone()))
+                    },
+                    MessageToMain::EvaluationError { reason, count } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: evaluation_error(worker: {worker_index}, count: {count}, reason: {reason})"),
+                        );
+
+                        None
+                    },
+                    MessageToMain::RandomParamResult { .. } | MessageToMain::EvaluateResult { .. } => unreachable!(),
+                };
+
+                if let Some((state_id, curr_params, prev_step)) = next_try {
+                    bases[worker_index][state_id] = (next_version, curr_params.clone());
+
+                    if channel.send(MessageFromMain::TryWithGradient {
+                        state_id,
+                        version: next_version,
+                        curr_params,
+                        prev_step,
+                        step_size: initial_step_size,
+                        step_moment,
+                        count: iter_per_worker,
+                        scales: scales.get(),
+                    }).is_err() {
+                        // TODO: revive this channel
+                    }
+
+                    next_version += 1;
+                }
+            }
+        }
+
+        if visualize && last_visualized.elapsed() >= VISUALIZE_INTERVAL {
+            config::visualizer(&states, &stats, &parameter_groups);
+            last_visualized = Instant::now();
+        }
+
+        if last_stats_log.elapsed().as_secs() >= 10 {
+            write_log(
+                write_logs_to.clone(),
+                "master",
+                &format!("stats: {}", stats.pretty_print_one_line()),
+            );
+            last_stats_log = Instant::now();
+        }
+
+        if last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+            write_checkpoint(&checkpoint, dimension, &states, &write_logs_to);
+            last_checkpoint = Instant::now();
+        }
+
+        // no need to run a busy loop
+        if !got_message {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    write_log(
+        write_logs_to.clone(),
+        "master",
+        &format!(
+            "{}: {}",
+            if stats.target_reached() { "target loss reached" } else { "evaluation budget exhausted" },
+            stats.pretty_print_one_line(),
+        ),
+    );
+
+    if visualize {
+        config::visualizer(&states, &stats, &parameter_groups);
+    }
+
+    write_checkpoint(&checkpoint, dimension, &states, &write_logs_to);
+
+    // the best state
+    let best_state = states.iter().min_by(|s1, s2| s1.loss.total_cmp(&s2.loss)).unwrap();
+
+    RunSummary {
+        best_params: T::to_f64_slice(&best_state.parameters).into_owned(),
+        best_loss: best_state.loss.to_f64(),
+        evaluations: stats.global.evaluations,
+        evaluations_to_target: stats.evaluations_to_target,
+        elapsed: stats.started_at.elapsed(),
+    }
+}
+
+// Each worker gets a `TryRandomParams` in `messages`, and the 2 farthest ones of the
+// workers' bests become the first states. With 1 worker, its best is the only state.
+fn run_random_phase<T: Float>(
+    channels: &[Channel<T>],
+    stats: &mut Stats,
+    messages: Vec<MessageFromMain<T>>,
+    write_logs_to: &Option<String>,
+) -> Vec<State<T>> {
+    distribute_messages(messages, channels).unwrap();
+
+    let mut good_random_params = vec![];
+
+    // waits until the workers finish trying random params
+    while good_random_params.len() < channels.len() {
+        let mut got_message = false;
+
+        for (worker_index, channel) in channels.iter().enumerate() {
+            if let Ok(msg) = channel.try_recv() {
+                got_message = true;
+
+                match msg {
+                    MessageToMain::RandomParamResult {
+                        best_params,
+                        best_loss,
+                        stats: worker_stats,
+                    } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: random_param_result(loss: {best_loss:.4}, evaluations: {})", worker_stats.evaluations),
+                        );
+                        stats.add_worker_stats(worker_index, &worker_stats);
+                        stats.update_best_loss(best_loss.to_f64());
+                        good_random_params.push((best_params, best_loss));
+                    },
+                    MessageToMain::EvaluationError { reason, count } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: evaluation_error(worker: {worker_index}, count: {count}, reason: {reason})"),
+                        );
+                    },
+                    _ => unreachable!(),
+                }
+            }
+
+            if channel.send(MessageFromMain::HealthCheck).is_err() {
+                // TODO: revive
+                write_log(
+                    write_logs_to.clone(),
+                    "master",
+                    "found a dead worker",
+                );
+            }
+        }
+
+        // no need to run a busy loop
+        if !got_message {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    let states = if good_random_params.len() == 1 {
+        let (params, loss) = good_random_params.pop().unwrap();
+        vec![State::new(0, params, None, loss)]
+    }
+
+    else {
+        let mut distances = vec![];
+
+        for i in 0..good_random_params.len() {
+            for j in (i + 1)..good_random_params.len() {
+                distances.push((i, j, get_distance_of_params(&good_random_params[i].0, &good_random_params[j].0)));
+            }
+        }
+
+        distances.sort_by(|(_, _, dist1), (_, _, dist2)| dist1.total_cmp(dist2));
+
+        let (farthest1, farthest2, _) = *distances.last().unwrap();
+
+        vec![
+            State::new(0, good_random_params[farthest1].0.clone(), None, good_random_params[farthest1].1),
+            State::new(1, good_random_params[farthest2].0.clone(), None, good_random_params[farthest2].1),
+        ]
+    };
+
+    write_log(
+        write_logs_to.clone(),
+        "master",
+        &format!("random phase done: {}", stats.pretty_print_one_line()),
+    );
+
+    states
+}
+
+// `base` + `step`, where `base` is (version, curr_params) of the `TryWithGradient` that the result answers
+fn apply_step<T: Float>(base: &(usize, Arc<[T]>), version: usize, step: &[T]) -> Vec<T> {
+    assert_eq!(version, base.0, "a result has to answer the last `TryWithGradient`");
+    let mut params = base.1.to_vec();
+    add_params(&mut params, step);
+
+    params
+}
+
+// The workers evaluate the points, and each point becomes a state.
+fn evaluate_initial_points<T: Float>(
+    channels: &[Channel<T>],
+    stats: &mut Stats,
+    points: Vec<Vec<f64>>,
+    write_logs_to: &Option<String>,
+) -> Vec<State<T>> {
+    let points = points.into_iter().map(
+        |point| point.into_iter().map(T::from_f64).collect::<Vec<_>>()
+    ).collect::<Vec<_>>();
+
+    let messages = points.iter().enumerate().map(
+        |(index, parameters)| MessageFromMain::Evaluate { index, parameters: parameters.clone() }
+    ).collect();
+
+    if distribute_messages(messages, channels).is_err() {
+        abort(write_logs_to, "a worker died before evaluating the initial points");
+    }
+
+    let mut losses = vec![None; points.len()];
+    let mut remaining = points.len();
+
+    while remaining > 0 {
+        let mut got_message = false;
+
+        for (worker_index, channel) in channels.iter().enumerate() {
+            if let Ok(msg) = channel.try_recv() {
+                got_message = true;
+
+                match msg {
+                    MessageToMain::EvaluateResult { index, loss, stats: worker_stats } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: evaluate_result(point: {index}, loss: {loss:.4})"),
+                        );
+                        stats.add_worker_stats(worker_index, &worker_stats);
+                        stats.update_best_loss(loss.to_f64());
+                        losses[index] = Some(loss);
+                        remaining -= 1;
+                    },
+                    MessageToMain::EvaluationError { reason, count } => {
+                        write_log(
+                            write_logs_to.clone(),
+                            "master",
+                            &format!("got message: evaluation_error(worker: {worker_index}, count: {count}, reason: {reason})"),
+                        );
+                    },
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        // no need to run a busy loop
+        if !got_message {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    write_log(
+        write_logs_to.clone(),
+        "master",
+        &format!("evaluated {} initial points: {}", points.len(), stats.pretty_print_one_line()),
+    );
+
+    points.into_iter().zip(losses).enumerate().map(
+        // every loss is set in the loop above
+        |(id, (parameters, loss))| State::new(id, parameters, None, loss.unwrap())
+    ).collect()
+}
+
+// a failure is logged, but it doesn't stop the optimizer
+fn write_checkpoint<T: Float>(
+    checkpoint: &Option<String>,
+    dimension: usize,
+    states: &[State<T>],
+    write_logs_to: &Option<String>,
+) {
+    if let Some(path) = checkpoint {
+        if let Err(e) = save_checkpoint(path, dimension, states) {
+            write_log(
+                write_logs_to.clone(),
+                "master",
+                &format!("cannot write the checkpoint: {}", e.render_error()),
+            );
+        }
+    }
+}
+
+// the number of parameters: the objective's own dimension or `Config.dimension`
+// a remote worker calls it, too, to check that it agrees with the master
+pub fn resolve_dimension(objective: &Objective, dimension: Option<usize>) -> Result<usize, String> {
+    let dimension = match (objective.dimension(), dimension) {
+        (Ok(Some(objective_dimension)), Some(dimension)) if objective_dimension != dimension => {
+            return Err(format!("the objective has {objective_dimension} parameters, but `dimension` is {dimension}"));
+        },
+        (Ok(Some(dimension)), _) | (Ok(None), Some(dimension)) => dimension,
+        (Ok(None), None) => {
+            return Err(String::from("the objective doesn't know its dimension: set `dimension` in config.rs"));
+        },
+        (Err(e), _) => {
+            return Err(format!("cannot load the objective: {}", e.render_error()));
+        },
+    };
+
+    if dimension == 0 {
+        return Err(String::from("dimension has to be at least 1"));
+    }
+
+    Ok(dimension)
+}
+
+fn abort(write_logs_to: &Option<String>, error_message: &str) -> ! {
+    write_log(
+        write_logs_to.clone(),
+        "master",
+        error_message,
+    );
+
+    panic!("{error_message}");
+}
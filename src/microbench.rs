@@ -0,0 +1,274 @@
+// `nonlinear_opt microbench`: measures the hot paths of the optimizer on large vectors.
+//
+// Build it with `--release`. The numbers are wall-clock time per iteration, so run it on an idle machine.
+
+use crate::config::default_config;
+use crate::master;
+use crate::objective::Objective;
+use crate::samples::lstm::{self, LstmSpec};
+use crate::subspace::Subspace;
+use crate::utils::{
+    add_params,
+    axpy,
+    fill_random_direction,
+    generate_random_params,
+    get_distance_of_params,
+    get_l2_norm,
+    mat_vec,
+    mat_vec_parallel,
+    mul_k_params,
+    sub_params,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::hint::black_box;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+// the dimension of the large-dimension benchmarks
+const LARGE_DIMENSION: usize = 1 << 20;
+
+pub fn run_microbench() {
+    bench_kernels();
+    bench_lstm();
+    bench_messages();
+    bench_large_objective();
+}
+
+// runs `f` `iterations` times, and prints the time per iteration
+fn measure<F: FnMut()>(name: &str, iterations: usize, mut f: F) -> Duration {
+    // warm up
+    f();
+
+    let started_at = Instant::now();
+
+    for _ in 0..iterations {
+        f();
+    }
+
+    let per_iteration = started_at.elapsed() / iterations as u32;
+    println!("{name:<48} {:>12.3} us/iter", per_iteration.as_secs_f64() * 1e6);
+
+    per_iteration
+}
+
+// the kernels of `utils.rs` vs plain index loops, which is how they were written before
+fn bench_kernels() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut a = generate_random_params::<f64, _>(&mut rng, LARGE_DIMENSION, 1.0);
+    let b = generate_random_params::<f64, _>(&mut rng, LARGE_DIMENSION, 1.0);
+
+    println!("kernels ({LARGE_DIMENSION} parameters)");
+
+    compare(
+        "l2 norm",
+        || { black_box(plain_l2_norm(black_box(&a))); },
+        || { black_box(get_l2_norm(black_box(&a))); },
+    );
+    compare(
+        "distance",
+        || { black_box(plain_distance(black_box(&a), black_box(&b))); },
+        || { black_box(get_distance_of_params(black_box(&a), black_box(&b))); },
+    );
+
+    // `a` changes in place, so every pair adds and subtracts the same vector
+    let mut plain = a.clone();
+    compare_mut(
+        "add, sub",
+        &mut plain,
+        &mut a,
+        |a| { plain_add(a, &b); plain_sub(a, &b); },
+        |a| { add_params(a, &b); sub_params(a, &b); },
+    );
+    compare_mut(
+        "multiply by k",
+        &mut plain,
+        &mut a,
+        |a| { plain_mul_k(a, black_box(1.0)); },
+        |a| { mul_k_params(a, black_box(1.0)); },
+    );
+
+    // a momentum step: `step += k * prev_step`. `k` is 0 so that `a` doesn't change, and the plain side
+    // scales its own buffer in place, so neither side copies inside the timed closure.
+    let mut weighted = b.clone();
+    compare_mut(
+        "axpy (vs multiply + add)",
+        &mut plain,
+        &mut a,
+        |a| { plain_mul_k(&mut weighted, black_box(0.0)); plain_add(a, &weighted); },
+        |a| { axpy(a, black_box(0.0), &b); },
+    );
+
+    let mut buffer = vec![0.0f64; LARGE_DIMENSION];
+    compare(
+        "random direction (vs allocating)",
+        || { black_box(generate_random_params::<f64, _>(&mut rng.clone(), LARGE_DIMENSION, 1.0)); },
+        || { fill_random_direction(&mut rng.clone(), &mut buffer, 1.0); black_box(&buffer); },
+    );
+}
+
+fn compare<F: FnMut(), G: FnMut()>(name: &str, plain: F, kernel: G) {
+    let plain = measure(&format!("{name}: plain"), 50, plain);
+    let kernel = measure(&format!("{name}: utils"), 50, kernel);
+
+    println!("speedup: {:.1}x", plain.as_secs_f64() / kernel.as_secs_f64().max(1e-12));
+}
+
+// `compare` for kernels that change a vector in place
+fn compare_mut<F: FnMut(&mut [f64]), G: FnMut(&mut [f64])>(
+    name: &str,
+    plain_v: &mut [f64],
+    kernel_v: &mut [f64],
+    mut plain: F,
+    mut kernel: G,
+) {
+    compare(
+        name,
+        || { plain(black_box(&mut *plain_v)); },
+        || { kernel(black_box(&mut *kernel_v)); },
+    );
+}
+
+// the plain kernels index on purpose
+#[allow(clippy::needless_range_loop)]
+fn plain_l2_norm(params: &[f64]) -> f64 {
+    let mut sum = 0.0;
+
+    for i in 0..params.len() {
+        sum += params[i] * params[i];
+    }
+
+    sum.sqrt()
+}
+
+fn plain_distance(p1: &[f64], p2: &[f64]) -> f64 {
+    let mut sum = 0.0;
+
+    for i in 0..p1.len() {
+        sum += (p1[i] - p2[i]) * (p1[i] - p2[i]);
+    }
+
+    sum.sqrt()
+}
+
+fn plain_add(params: &mut [f64], val: &[f64]) {
+    for i in 0..params.len() {
+        params[i] += val[i];
+    }
+}
+
+fn plain_sub(params: &mut [f64], val: &[f64]) {
+    for i in 0..params.len() {
+        params[i] -= val[i];
+    }
+}
+
+#[allow(clippy::needless_range_loop)]
+fn plain_mul_k(params: &mut [f64], k: f64) {
+    for i in 0..params.len() {
+        params[i] *= k;
+    }
+}
+
+// the mat-vecs of an LSTM time step (1024 x 1280), and a whole time step of `samples::lstm`
+fn bench_lstm() {
+    let (rows, columns) = (1024, 1280);
+    let mut rng = StdRng::seed_from_u64(0);
+    let weight = generate_random_params::<f32, _>(&mut rng, rows * columns, 1.0);
+    let input = generate_random_params::<f32, _>(&mut rng, columns, 1.0);
+    let bias = generate_random_params::<f32, _>(&mut rng, rows, 1.0);
+    let mut out = vec![0.0; rows];
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+    println!("\nlstm");
+
+    compare(
+        "mat-vec 1024 x 1280",
+        || { black_box(plain_mat_vec(black_box(&weight), black_box(&input), black_box(&bias))); },
+        || { mat_vec(black_box(&weight), black_box(&input), black_box(&bias), &mut out); black_box(&out); },
+    );
+    measure(&format!("mat-vec 1024 x 1280, {threads} threads"), 50, || {
+        mat_vec_parallel(black_box(&weight), black_box(&input), black_box(&bias), &mut out, threads);
+        black_box(&out);
+    });
+
+    let spec = LstmSpec::default();
+    let parameters = generate_random_params::<f32, _>(&mut rng, spec.param_size(), 100.0);
+    let x = lstm::one_hot_encode(b'a' as usize, spec.input_dimension);
+    let (mut cell_state, mut hidden_state, _) = lstm::time_step(&spec, &parameters, &[0.0; 1024], &[0.0; 1024], &x, false);
+    let mut output = vec![0.0; 256];
+    let mut scratch = lstm::Scratch::new(&spec);
+
+    measure("time step, allocating", 50, || {
+        black_box(lstm::time_step(&spec, &parameters, &cell_state, &hidden_state, &x, true));
+    });
+    measure("time step, in place", 50, || {
+        lstm::time_step_in_place(&parameters, &mut cell_state, &mut hidden_state, &x, Some(&mut output), &mut scratch);
+        black_box(&output);
+    });
+}
+
+// the mat-vec of the gates of `samples::lstm`, as it was written before `utils::mat_vec`
+fn plain_mat_vec(weight: &[f32], input: &[f32], bias: &[f32]) -> Vec<f32> {
+    (0..bias.len()).map(
+        |index1| (0..input.len()).map(
+            |index2| weight[index1 * input.len() + index2] * input[index2]
+        ).sum::<f32>() + bias[index1]
+    ).collect()
+}
+
+// sending the parameters of a state to a worker: a copy per message vs a shared buffer
+fn bench_messages() {
+    let parameters = vec![0.5f64; LARGE_DIMENSION];
+    let shared: Arc<[f64]> = parameters.clone().into();
+    let (tx_vec, rx_vec) = mpsc::channel::<Vec<f64>>();
+    let (tx_arc, rx_arc) = mpsc::channel::<Arc<[f64]>>();
+
+    println!("\nmessages ({LARGE_DIMENSION} parameters)");
+
+    let copied = measure("send a copy of the parameters", 200, || {
+        tx_vec.send(parameters.clone()).unwrap();
+        black_box(rx_vec.recv().unwrap());
+    });
+
+    let shared = measure("send a shared buffer of the parameters", 200, || {
+        tx_arc.send(shared.clone()).unwrap();
+        black_box(rx_arc.recv().unwrap());
+    });
+
+    println!("speedup: {:.1}x", copied.as_secs_f64() / shared.as_secs_f64().max(1e-12));
+}
+
+fn sum_of_squares(parameters: &[f64]) -> f64 {
+    parameters.iter().map(|p| p * p).sum()
+}
+
+// the whole optimizer on a cheap objective, so that the overhead of the optimizer dominates
+fn bench_large_objective() {
+    println!("\noptimizer ({LARGE_DIMENSION} parameters, sum of squares)");
+
+    for (name, subspace) in [
+        ("full", Subspace::Full),
+        ("random subset of 64", Subspace::RandomSubset { size: 64 }),
+        ("block of 64", Subspace::Block { size: 64 }),
+    ] {
+        let mut config = default_config();
+        config.objective = Objective::NativeF64(sum_of_squares);
+        config.dimension = Some(LARGE_DIMENSION);
+        config.num_workers = 4;
+        config.iter_per_worker = 16;
+        config.max_evaluations = Some(2_000);
+        config.subspace = subspace;
+        config.seed = Some(0);
+        config.visualize = false;
+        config.write_logs_to = None;
+        config.listen_for_workers = None;
+
+        let summary = master::run(config);
+
+        println!(
+            "{name:<48} {:>12.1} evals/s (loss: {})",
+            summary.evaluations as f64 / summary.elapsed.as_secs_f64(),
+            summary.best_loss,
+        );
+    }
+}
@@ -1,39 +1,65 @@
-use crate::config::{
-    default_config,
-    f,
-    ParamType,
-    PARAM_SIZE,
-    VERY_BIG_LOSS,
-};
+use crate::config::Config;
+use crate::eval::Evaluator;
+use crate::float::Float;
+use crate::groups::{segments, ParameterGroup};
 use crate::log::write_log;
+use crate::objective::Objective;
+use crate::sampling::{InitSampler, InitStrategy, Partition};
+use crate::stats::EvalStats;
+use crate::subspace::Subspace;
 use crate::utils::{
     add_params,
-    generate_random_params,
+    axpy,
+    fill_random_direction,
+    fill_scaled_random_direction,
     get_l2_norm,
     mul_k_params,
-    sub_params,
+    mul_params,
 };
-use std::sync::mpsc;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
 
-pub enum MessageFromMain {
+pub enum MessageFromMain<T: Float> {
     TryRandomParams {
-        param_l2_norm: ParamType,
+        // only `InitStrategy::RandomL2Norm` uses this
+        param_l2_norm: T,
+
+        // the number of parameters
+        dimension: usize,
         count: usize,
+        strategy: InitStrategy,
+        partition: Partition,
     },
     TryWithGradient {
         state_id: usize,
-        curr_params: Vec<ParamType>,
-        prev_step: Option<Vec<ParamType>>,
+
+        // identifies `curr_params`. The result echoes it, and the master keeps `curr_params`
+        // until the result arrives, so the result can be a delta against it.
+        version: usize,
+
+        // the master shares these buffers with its states (and with other messages)
+        curr_params: Arc<[T]>,
+        prev_step: Option<Arc<[T]>>,
 
         // a value between 0 ~ 1
         // new_step = prev_step * moment + rand * (1 - moment)
-        step_moment: ParamType,
+        step_moment: T,
 
         // this is used only when `prev_step` is None
-        step_size: ParamType,
+        step_size: T,
         count: usize,
+
+        // per-parameter scales of the random steps (see `scaling.rs`)
+        // None: isotropic
+        scales: Option<Vec<T>>,
+    },
+
+    // evaluates `parameters` once
+    Evaluate {
+        index: usize,
+        parameters: Vec<T>,
     },
 
     // There's no need to respond to this message.
@@ -42,64 +68,131 @@ pub enum MessageFromMain {
     HealthCheck,
 }
 
-pub enum MessageToMain {
+// Every result carries `stats`: the counters the worker accumulated while working on the message.
+pub enum MessageToMain<T: Float> {
     RandomParamResult {
-        best_params: Vec<ParamType>,
-        best_loss: ParamType,
+        best_params: Vec<T>,
+
+        // it's infinity if `f` didn't return a finite value for any candidate
+        best_loss: T,
+        stats: EvalStats,
     },
+    // best_params = curr_params of `version` + `step`
     WithGradientResult {
         state_id: usize,
-        best_params: Vec<ParamType>,
-        best_loss: ParamType,
-
-        // best - previous
-        step: Vec<ParamType>,
+        version: usize,
+        best_loss: T,
+        step: Vec<T>,
+        stats: EvalStats,
     },
     WithGradientResultFailure {
         state_id: usize,
+        stats: EvalStats,
+    },
+
+    // `WithGradientResult` in a subspace mode (see `subspace.rs`)
+    // best_params = curr_params of `version` + `delta` at `indices`
+    SparseResult {
+        state_id: usize,
+        version: usize,
+        indices: Vec<usize>,
+        delta: Vec<T>,
+        best_loss: T,
+        stats: EvalStats,
+    },
+    EvaluateResult {
+        index: usize,
+
+        // it's infinity if `f` didn't return a finite value
+        loss: T,
+        stats: EvalStats,
+    },
+
+    // Calls to `f` panicked, timed out or returned non-finite values while working on a message.
+    // It's sent right before the result of the message.
+    EvaluationError {
+        // the first error
+        reason: String,
+
+        // the number of errors
+        count: usize,
     },
 }
 
-pub struct Channel {
-    tx_from_main: mpsc::Sender<MessageFromMain>,
-    rx_to_main: mpsc::Receiver<MessageToMain>,
+// the part of `Config` that workers need
+#[derive(Clone)]
+pub struct WorkerConfig {
+    pub objective: Objective,
+    pub eval_timeout: Option<Duration>,
+    pub penalty_loss: f64,
+    pub write_logs_to: Option<String>,
+
+    // each worker derives its rng from `seed` and its index
+    pub seed: Option<u64>,
+    pub parameter_groups: Vec<ParameterGroup>,
+    pub subspace: Subspace,
 }
 
-impl Channel {
-    pub fn send(&self, msg: MessageFromMain) -> Result<(), mpsc::SendError<MessageFromMain>> {
-        self.tx_from_main.send(msg)
+impl WorkerConfig {
+    pub fn from_config(config: &Config) -> Self {
+        WorkerConfig {
+            objective: config.objective.clone(),
+            eval_timeout: config.eval_timeout,
+            penalty_loss: config.penalty_loss,
+            write_logs_to: config.write_logs_to.clone(),
+            seed: config.seed,
+            parameter_groups: config.parameter_groups.clone(),
+            subspace: config.subspace.clone(),
+        }
     }
 
-    pub fn try_recv(&self) -> Result<MessageToMain, mpsc::TryRecvError> {
-        self.rx_to_main.try_recv()
+    fn rng(&self, worker_index: usize) -> StdRng {
+        match self.seed {
+            // with `seed + worker_index`, runs with seed 0 and seed 1 would share most of their workers' streams
+            Some(seed) => StdRng::seed_from_u64(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15).wrapping_add(worker_index as u64)),
+            None => StdRng::from_entropy(),
+        }
     }
+}
+
+pub struct Channel<T: Float> {
+    tx_from_main: mpsc::Sender<MessageFromMain<T>>,
+    rx_to_main: mpsc::Receiver<MessageToMain<T>>,
+}
 
-    pub fn block_recv(&self) -> Result<MessageToMain, mpsc::RecvError> {
-        self.rx_to_main.recv()
+impl<T: Float> Channel<T> {
+    pub fn new(tx_from_main: mpsc::Sender<MessageFromMain<T>>, rx_to_main: mpsc::Receiver<MessageToMain<T>>) -> Self {
+        Channel { tx_from_main, rx_to_main }
+    }
+
+    pub fn send(&self, msg: MessageFromMain<T>) -> Result<(), mpsc::SendError<MessageFromMain<T>>> {
+        self.tx_from_main.send(msg)
+    }
+
+    pub fn try_recv(&self) -> Result<MessageToMain<T>, mpsc::TryRecvError> {
+        self.rx_to_main.try_recv()
     }
 }
 
-pub fn init_channels(n: usize) -> Vec<Channel> {
-    (0..n).map(|_| init_channel()).collect()
+pub fn init_channels<T: Float>(n: usize, worker_config: &WorkerConfig) -> Vec<Channel<T>> {
+    (0..n).map(|worker_index| init_channel(worker_config.clone(), worker_index)).collect()
 }
 
-pub fn init_channel() -> Channel {
+pub fn init_channel<T: Float>(worker_config: WorkerConfig, worker_index: usize) -> Channel<T> {
     let (tx_to_main, rx_to_main) = mpsc::channel();
     let (tx_from_main, rx_from_main) = mpsc::channel();
 
     thread::spawn(move || {
-        event_loop(tx_to_main, rx_from_main);
+        event_loop(tx_to_main, rx_from_main, worker_config, worker_index);
     });
 
-    Channel {
-        rx_to_main, tx_from_main
-    }
+    Channel::new(tx_from_main, rx_to_main)
 }
 
-pub fn distribute_messages(
-    messages: Vec<MessageFromMain>,
-    channels: &[Channel],
-) -> Result<(), mpsc::SendError<MessageFromMain>> {
+pub fn distribute_messages<T: Float>(
+    messages: Vec<MessageFromMain<T>>,
+    channels: &[Channel<T>],
+) -> Result<(), mpsc::SendError<MessageFromMain<T>>> {
     for (index, message) in messages.into_iter().enumerate() {
         channels[index % channels.len()].send(message)?;
     }
@@ -107,10 +200,83 @@ pub fn distribute_messages(
     Ok(())
 }
 
-pub fn event_loop(tx_to_main: mpsc::Sender<MessageToMain>, rx_from_main: mpsc::Receiver<MessageFromMain>) {
+// The first step of a state is `step_size` in the scaled space: each element is multiplied by its scale.
+fn random_first_step<T: Float, R: Rng>(rng: &mut R, step: &mut [T], step_size: T, scales: Option<&[T]>) {
+    fill_random_direction(rng, step, step_size);
+
+    if let Some(scales) = scales {
+        mul_params(step, scales);
+    }
+}
+
+// The later steps keep the l2 norm of the previous step, so the scales only decide the direction.
+fn random_step<T: Float, R: Rng>(rng: &mut R, step: &mut [T], l2_norm: T, scales: Option<&[T]>) {
+    match scales {
+        Some(scales) => fill_scaled_random_direction(rng, step, scales, l2_norm),
+        None => fill_random_direction(rng, step, l2_norm),
+    }
+}
+
+// A step at `indices`: the momentum of `prev_step` at `indices` + a random step, rescaled to `step_size`.
+// Without momentum, it's `random_first_step` at `indices`.
+fn subspace_step<T: Float, R: Rng>(
+    rng: &mut R,
+    indices: &[usize],
+    prev_step: Option<&[T]>,
+    step_moment: T,
+    step_size: T,
+    scales: Option<&[T]>,
+) -> Vec<T> {
+    let scales = scales.map(|scales| indices.iter().map(|index| scales[*index]).collect::<Vec<_>>());
+    let prev_step = match prev_step {
+        Some(prev_step) => indices.iter().map(|index| prev_step[*index]).collect::<Vec<_>>(),
+        None => vec![],
+    };
+    let mut step = vec![T::ZERO; indices.len()];
+
+    if get_l2_norm(&prev_step) == T::ZERO {
+        random_first_step(rng, &mut step, step_size, scales.as_deref());
+        return step;
+    }
+
+    random_step(rng, &mut step, (T::ONE - step_moment) * step_size, scales.as_deref());
+    axpy(&mut step, step_moment, &prev_step);
+
+    let curr_step_size = get_l2_norm(&step);
+    mul_k_params(&mut step, step_size / curr_step_size);
+
+    step
+}
+
+// The candidates share a buffer, so the best one is copied out of it.
+fn keep_best<T: Float>(curr_best: &mut Option<(Vec<T>, T)>, step: &[T], loss: T) {
+    match curr_best {
+        Some((best_step, best_loss)) => {
+            if loss < *best_loss {
+                best_step.copy_from_slice(step);
+                *best_loss = loss;
+            }
+        },
+        None => {
+            *curr_best = Some((step.to_vec(), loss));
+        },
+    }
+}
+
+// It returns when the master hangs up.
+pub fn event_loop<T: Float>(
+    tx_to_main: mpsc::Sender<MessageToMain<T>>,
+    rx_from_main: mpsc::Receiver<MessageFromMain<T>>,
+    worker_config: WorkerConfig,
+    worker_index: usize,
+) {
     let worker_id = rand::random::<u32>() & 0xfff_ffff;
     let worker_name = format!("worker-{worker_id:x}");
-    let (_, _, _, _, _, _, write_logs_to, _) = default_config();
+    let mut rng = worker_config.rng(worker_index);
+    let write_logs_to = worker_config.write_logs_to;
+    let parameter_groups = worker_config.parameter_groups;
+    let subspace = worker_config.subspace;
+    let mut evaluator = Evaluator::new(worker_config.objective, worker_config.eval_timeout, T::from_f64(worker_config.penalty_loss));
 
     write_log(
         write_logs_to.clone(),
@@ -118,168 +284,373 @@ pub fn event_loop(tx_to_main: mpsc::Sender<MessageToMain>, rx_from_main: mpsc::R
         &format!("hello from {worker_name}"),
     );
 
-    loop {
-        while let Ok(msg) = rx_from_main.try_recv() {
-            match msg {
-                MessageFromMain::TryRandomParams {
-                    count,
-                    param_l2_norm,
-                } => {
-                    write_log(
-                        write_logs_to.clone(),
-                        &worker_name,
-                        &format!("got message: try_random_params"),
-                    );
-                    let mut curr_best_params = generate_random_params(
-                        PARAM_SIZE,
-                        param_l2_norm * (rand::random::<ParamType>() + 0.5),
-                    );
-                    let mut curr_best_loss = f(&curr_best_params);
-
-                    for _ in 0..(count - 1) {
-                        let new_params = generate_random_params(
-                            PARAM_SIZE,
-                            param_l2_norm * (rand::random::<ParamType>() + 0.5),
-                        );
-                        let new_loss = f(&new_params);
-
+    // it blocks until the next message arrives, so it's not a busy loop
+    while let Ok(msg) = rx_from_main.recv() {
+        match msg {
+            MessageFromMain::TryRandomParams {
+                count,
+                param_l2_norm,
+                dimension,
+                strategy,
+                partition,
+            } => {
+                write_log(
+                    write_logs_to.clone(),
+                    &worker_name,
+                    "got message: try_random_params",
+                );
+                let mut sampler = InitSampler::new(&strategy, param_l2_norm, dimension, count, &partition, &mut rng);
+                let mut curr_best_params = sampler.next(&mut rng);
+                let mut curr_best_loss = evaluator.eval(&curr_best_params).unwrap_or(T::INFINITY);
+
+                for _ in 0..(count - 1) {
+                    let new_params = sampler.next(&mut rng);
+
+                    if let Some(new_loss) = evaluator.eval(&new_params) {
                         if new_loss < curr_best_loss {
                             curr_best_params = new_params;
                             curr_best_loss = new_loss;
                         }
                     }
+                }
+
+                report_errors(&mut evaluator, &tx_to_main, &write_logs_to, &worker_name);
+                if tx_to_main.send(MessageToMain::RandomParamResult {
+                    best_params: curr_best_params,
+                    best_loss: curr_best_loss,
+                    stats: evaluator.take_stats(),
+                }).is_err() {
+                    // the master hung up
+                    return;
+                }
+            },
+            MessageFromMain::TryWithGradient {
+                state_id,
+                version,
+                curr_params,
+                prev_step,
+                step_moment,
+                step_size,
+                count,
+                scales,
+            } if subspace != Subspace::Full => {
+                write_log(
+                    write_logs_to.clone(),
+                    &worker_name,
+                    "got message: try_with_gradient(subspace)",
+                );
+                assert!(T::ZERO <= step_moment && step_moment <= T::ONE);
+
+                let segments = segments(&parameter_groups, curr_params.len(), step_size, step_moment).into_iter().filter(
+                    |segment| !segment.frozen
+                ).collect::<Vec<_>>();
+                let free = segments.iter().flat_map(|segment| segment.range.clone()).collect::<Vec<_>>();
+
+                let mut curr_best: Option<(Vec<usize>, Vec<T>, T)> = None;
+                let mut curr_params = curr_params.to_vec();
+
+                for _ in 0..count {
+                    let indices = subspace.draw_coordinates(&mut rng, &free);
+                    let mut delta = Vec::with_capacity(indices.len());
+
+                    // each segment's part of the step has the segment's step controls
+                    // `indices` is sorted, so the indices of a segment are consecutive, and `delta` follows `indices`
+                    for segment in segments.iter() {
+                        let start = indices.partition_point(|index| *index < segment.range.start);
+                        let end = indices.partition_point(|index| *index < segment.range.end);
+
+                        if start < end {
+                            delta.extend(subspace_step(
+                                &mut rng,
+                                &indices[start..end],
+                                prev_step.as_deref(),
+                                segment.step_moment,
+                                segment.step_size,
+                                scales.as_deref(),
+                            ));
+                        }
+                    }
 
-                    tx_to_main.send(MessageToMain::RandomParamResult {
-                        best_params: curr_best_params,
-                        best_loss: curr_best_loss,
-                    }).unwrap();
-                },
-                MessageFromMain::TryWithGradient {
-                    state_id,
-                    curr_params,
-                    prev_step: Some(prev_step),
-                    step_moment,
-                    step_size: _,
-                    count,
-                } => {
-                    write_log(
-                        write_logs_to.clone(),
-                        &worker_name,
-                        &format!("got message: try_with_gradient(prev_step: Some(...))"),
-                    );
-                    assert!(0.0 <= step_moment && step_moment <= 1.0);
+                    // changes the parameters in place, and restores them after the evaluation
+                    let old_values = indices.iter().map(|index| curr_params[*index]).collect::<Vec<_>>();
 
-                    let prev_step_size = get_l2_norm(&prev_step);
+                    for (index, d) in indices.iter().zip(delta.iter()) {
+                        curr_params[*index] += *d;
+                    }
 
-                    // new step = weighted_prev_step + rand
-                    let mut weighted_prev_step = prev_step.clone();
-                    mul_k_params(&mut weighted_prev_step, step_moment);
+                    let new_loss = evaluator.eval(&curr_params);
 
-                    let rand_step_size = (1.0 - step_moment) * prev_step_size;
+                    for (index, old_value) in indices.iter().zip(old_values) {
+                        curr_params[*index] = old_value;
+                    }
 
-                    let mut curr_best_loss = VERY_BIG_LOSS;
-                    let mut curr_best_params = curr_params.clone();
+                    if let Some(new_loss) = new_loss {
+                        if curr_best.as_ref().map(|(_, _, best_loss)| new_loss < *best_loss).unwrap_or(true) {
+                            curr_best = Some((indices, delta, new_loss));
+                        }
+                    }
+                }
+
+                report_errors(&mut evaluator, &tx_to_main, &write_logs_to, &worker_name);
+
+                let message = match curr_best {
+                    // every candidate was rejected
+                    None => MessageToMain::WithGradientResultFailure { state_id, stats: evaluator.take_stats() },
+                    Some((indices, delta, best_loss)) => MessageToMain::SparseResult {
+                        state_id,
+                        version,
+                        indices,
+                        delta,
+                        best_loss,
+                        stats: evaluator.take_stats(),
+                    },
+                };
+
+                if tx_to_main.send(message).is_err() {
+                    // the master hung up
+                    return;
+                }
+            },
+            MessageFromMain::TryWithGradient {
+                state_id,
+                version,
+                curr_params,
+                prev_step: Some(prev_step),
+                step_moment,
+                step_size,
+                count,
+                scales,
+            } => {
+                write_log(
+                    write_logs_to.clone(),
+                    &worker_name,
+                    "got message: try_with_gradient(prev_step: Some(...))",
+                );
+                assert!(T::ZERO <= step_moment && step_moment <= T::ONE);
+
+                let segments = segments(&parameter_groups, curr_params.len(), step_size, step_moment);
+                let prev_step_sizes = segments.iter().map(
+                    |segment| get_l2_norm(&prev_step[segment.range.clone()])
+                ).collect::<Vec<_>>();
+
+                // new step = weighted_prev_step + rand
+                let mut weighted_prev_step = prev_step.to_vec();
+
+                for segment in segments.iter() {
+                    mul_k_params(
+                        &mut weighted_prev_step[segment.range.clone()],
+                        if segment.frozen { T::ZERO } else { segment.step_moment },
+                    );
+                }
 
-                    for _ in 0..count {
-                        let d_step = generate_random_params(
-                            PARAM_SIZE,
-                            rand_step_size,
-                        );
+                // (step, loss)
+                let mut curr_best: Option<(Vec<T>, T)> = None;
 
-                        let mut new_step = weighted_prev_step.clone();
-                        add_params(&mut new_step, &d_step);
+                // they're reused by every candidate
+                let mut new_params = curr_params.to_vec();
+                let mut new_step = vec![T::ZERO; curr_params.len()];
+                let mut d_step = vec![T::ZERO; curr_params.len()];
 
-                        let new_step_size = get_l2_norm(&new_step);
-                        mul_k_params(&mut new_step, prev_step_size / new_step_size);
+                for _ in 0..count {
+                    new_step.copy_from_slice(&weighted_prev_step);
 
-                        let mut new_params = curr_params.clone();
-                        add_params(&mut new_params, &new_step);
+                    for (segment, prev_step_size) in segments.iter().zip(prev_step_sizes.iter()) {
+                        if segment.frozen {
+                            continue;
+                        }
 
-                        let new_loss = f(&new_params);
+                        let range = segment.range.clone();
+                        let scales = scales.as_ref().map(|scales| &scales[range.clone()]);
 
-                        if new_loss < curr_best_loss {
-                            curr_best_loss = new_loss;
-                            curr_best_params = new_params;
+                        // the previous step didn't move this segment (e.g. it was frozen)
+                        if *prev_step_size == T::ZERO {
+                            random_first_step(&mut rng, &mut new_step[range], segment.step_size, scales);
+                            continue;
                         }
-                    }
 
-                    if curr_best_params == curr_params {
-                        tx_to_main.send(MessageToMain::WithGradientResultFailure { state_id }).unwrap();
-                    }
+                        let rand_step_size = (T::ONE - segment.step_moment) * *prev_step_size;
+                        random_step(
+                            &mut rng,
+                            &mut d_step[range.clone()],
+                            rand_step_size,
+                            scales,
+                        );
 
-                    else {
-                        let mut calc_step = curr_best_params.clone();
-                        sub_params(&mut calc_step, &curr_params);
+                        add_params(&mut new_step[range.clone()], &d_step[range.clone()]);
 
-                        tx_to_main.send(MessageToMain::WithGradientResult {
-                            state_id,
-                            best_params: curr_best_params,
-                            best_loss: curr_best_loss,
-                            step: calc_step,
-                        }).unwrap();
+                        let new_step_size = get_l2_norm(&new_step[range.clone()]);
+                        mul_k_params(&mut new_step[range], *prev_step_size / new_step_size);
                     }
-                },
-                MessageFromMain::TryWithGradient {
-                    state_id,
-                    curr_params,
-                    prev_step: None,
-                    step_moment: _,
-                    step_size,
-                    count,
-                } => {
-                    write_log(
-                        write_logs_to.clone(),
-                        &worker_name,
-                        &format!("got message: try_with_gradient(prev_step: None)"),
-                    );
-
-                    let mut curr_best_params = curr_params.clone();
-                    let mut curr_best_loss = VERY_BIG_LOSS;
-
-                    for _ in 0..count {
-                        let new_step = generate_random_params(
-                            PARAM_SIZE,
-                            step_size,
-                        );
-
-                        let mut new_params = curr_params.clone();
-                        add_params(&mut new_params, &new_step);
 
-                        let new_loss = f(&new_params);
+                    new_params.copy_from_slice(&curr_params);
+                    add_params(&mut new_params, &new_step);
 
-                        if new_loss < curr_best_loss {
-                            curr_best_loss = new_loss;
-                            curr_best_params = new_params;
-                        }
+                    if let Some(new_loss) = evaluator.eval(&new_params) {
+                        keep_best(&mut curr_best, &new_step, new_loss);
                     }
+                }
+
+                report_errors(&mut evaluator, &tx_to_main, &write_logs_to, &worker_name);
+
+                let message = match curr_best {
+                    // every candidate was rejected
+                    None => MessageToMain::WithGradientResultFailure { state_id, stats: evaluator.take_stats() },
+                    Some((step, best_loss)) => MessageToMain::WithGradientResult {
+                        state_id,
+                        version,
+                        best_loss,
+                        step,
+                        stats: evaluator.take_stats(),
+                    },
+                };
+
+                if tx_to_main.send(message).is_err() {
+                    // the master hung up
+                    return;
+                }
+            },
+            MessageFromMain::TryWithGradient {
+                state_id,
+                version,
+                curr_params,
+                prev_step: None,
+                step_moment,
+                step_size,
+                count,
+                scales,
+            } => {
+                write_log(
+                    write_logs_to.clone(),
+                    &worker_name,
+                    "got message: try_with_gradient(prev_step: None)",
+                );
+
+                let segments = segments(&parameter_groups, curr_params.len(), step_size, step_moment);
+
+                // (step, loss)
+                let mut curr_best: Option<(Vec<T>, T)> = None;
+
+                // they're reused by every candidate
+                let mut new_params = curr_params.to_vec();
+                let mut new_step = vec![T::ZERO; curr_params.len()];
+
+                // if the objective knows its gradient, the first candidate goes downhill
+                let gradient_step = evaluator.gradient(&curr_params).and_then(|mut gradient| {
+                    let gradient_size = get_l2_norm(&gradient);
+
+                    if gradient_size > T::ZERO && gradient_size.is_finite() {
+                        for segment in segments.iter() {
+                            let gradient = &mut gradient[segment.range.clone()];
+                            let gradient_size = get_l2_norm(gradient);
+
+                            if segment.frozen || gradient_size == T::ZERO {
+                                mul_k_params(gradient, T::ZERO);
+                            }
+
+                            else {
+                                mul_k_params(gradient, -segment.step_size / gradient_size);
+                            }
+                        }
 
-                    if curr_best_params == curr_params {
-                        tx_to_main.send(MessageToMain::WithGradientResultFailure { state_id }).unwrap();
+                        Some(gradient)
+                    } else {
+                        None
+                    }
+                });
+
+                for i in 0..count {
+                    match &gradient_step {
+                        Some(gradient_step) if i == 0 => {
+                            new_step.copy_from_slice(gradient_step);
+                        },
+
+                        // the frozen segments stay zero
+                        _ => {
+                            for segment in segments.iter().filter(|segment| !segment.frozen) {
+                                let range = segment.range.clone();
+
+                                random_first_step(
+                                    &mut rng,
+                                    &mut new_step[range.clone()],
+                                    segment.step_size,
+                                    scales.as_ref().map(|scales| &scales[range]),
+                                );
+                            }
+                        },
                     }
 
-                    else {
-                        let mut calc_step = curr_best_params.clone();
-                        sub_params(&mut calc_step, &curr_params);
+                    new_params.copy_from_slice(&curr_params);
+                    add_params(&mut new_params, &new_step);
 
-                        tx_to_main.send(MessageToMain::WithGradientResult {
-                            state_id,
-                            best_params: curr_best_params,
-                            best_loss: curr_best_loss,
-                            step: calc_step,
-                        }).unwrap();
+                    if let Some(new_loss) = evaluator.eval(&new_params) {
+                        keep_best(&mut curr_best, &new_step, new_loss);
                     }
-                },
-                MessageFromMain::HealthCheck => {
-                    write_log(
-                        write_logs_to.clone(),
-                        &worker_name,
-                        &format!("got message: health_check"),
-                    );
-                },
-            }
+                }
+
+                report_errors(&mut evaluator, &tx_to_main, &write_logs_to, &worker_name);
+
+                let message = match curr_best {
+                    // every candidate was rejected
+                    None => MessageToMain::WithGradientResultFailure { state_id, stats: evaluator.take_stats() },
+                    Some((step, best_loss)) => MessageToMain::WithGradientResult {
+                        state_id,
+                        version,
+                        best_loss,
+                        step,
+                        stats: evaluator.take_stats(),
+                    },
+                };
+
+                if tx_to_main.send(message).is_err() {
+                    // the master hung up
+                    return;
+                }
+            },
+            MessageFromMain::Evaluate { index, parameters } => {
+                write_log(
+                    write_logs_to.clone(),
+                    &worker_name,
+                    "got message: evaluate",
+                );
+                let loss = evaluator.eval(&parameters).unwrap_or(T::INFINITY);
+
+                report_errors(&mut evaluator, &tx_to_main, &write_logs_to, &worker_name);
+                if tx_to_main.send(MessageToMain::EvaluateResult {
+                    index,
+                    loss,
+                    stats: evaluator.take_stats(),
+                }).is_err() {
+                    // the master hung up
+                    return;
+                }
+            },
+            MessageFromMain::HealthCheck => {
+                write_log(
+                    write_logs_to.clone(),
+                    &worker_name,
+                    "got message: health_check",
+                );
+            },
         }
+    }
+}
 
-        // there's no need to run a busy loop
-        thread::sleep(Duration::from_millis(3000));
+fn report_errors<T: Float>(
+    evaluator: &mut Evaluator<T>,
+    tx_to_main: &mpsc::Sender<MessageToMain<T>>,
+    write_logs_to: &Option<String>,
+    worker_name: &str,
+) {
+    if let Some((e, count)) = evaluator.take_errors() {
+        let reason = e.render_error();
+
+        write_log(
+            write_logs_to.clone(),
+            worker_name,
+            &format!("{count} evaluation(s) failed, first error: {reason}"),
+        );
+
+        // if the master hung up, the worker notices it when it sends the result
+        let _ = tx_to_main.send(MessageToMain::EvaluationError { reason, count });
     }
 }
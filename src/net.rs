@@ -0,0 +1,879 @@
+// Workers in other processes (or on other machines) talk to the master over TCP.
+//
+// The master listens on `Config.listen_for_workers` and waits until `Config.num_workers` workers connect.
+// A remote worker is this binary, run with `nonlinear_opt worker <master address>`. It has to be
+// compiled with the same `config.rs` as the master, because it evaluates its own `config.objective`.
+// Both ends use the precision of the objective, so they agree on the size of floats.
+//
+// Both ends bridge a TCP stream to a pair of mpsc channels, so `multi::Channel` and
+// `multi::event_loop` don't know whether the other end is a thread or a remote process.
+//
+// handshake (before any frame, u64s, little endian)
+//   master -> worker: size of a float (`Float::BYTES`), dimension, index of the worker
+//   worker -> master: size of a float, dimension
+//   A worker built with another `config.rs` may use another precision or dimension, and it would
+//   misread every frame. So both ends compare the sizes and the dimensions: on a mismatch, the worker
+//   returns an error, and the master closes the connection and keeps waiting for another worker.
+//   A worker derives its rng from `Config.seed` and its index, like a local worker does.
+//
+// wire format
+//   frame: length of payload (u32, little endian) + payload
+//   payload: tag of the message (u8) + fields of the message
+//   integers: u64, little endian
+//   floats: `f32::to_le_bytes` or `f64::to_le_bytes`, depending on `Objective::precision`
+//           (except the fields of `InitStrategy`, which are always `f64`)
+//   vectors and strings: length (u64) + elements
+
+use crate::config::Config;
+use crate::float::{Float, Precision};
+use crate::log::write_log;
+use crate::master::resolve_dimension;
+use crate::multi::{
+    event_loop,
+    Channel,
+    MessageFromMain,
+    MessageToMain,
+    WorkerConfig,
+};
+use crate::sampling::{InitStrategy, Partition};
+use crate::stats::EvalStats;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// a remote worker gives up if it cannot connect to the master for this long
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn init_remote_channels<T: Float>(
+    address: &str,
+    n: usize,
+    dimension: usize,
+    write_logs_to: &Option<String>,
+) -> io::Result<Vec<Channel<T>>> {
+    let listener = TcpListener::bind(address)?;
+    let mut channels = Vec::with_capacity(n);
+
+    write_log(
+        write_logs_to.clone(),
+        "master",
+        &format!("waiting for {n} remote workers at {address}"),
+    );
+
+    while channels.len() < n {
+        let (mut stream, peer) = listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        let handshake = [T::BYTES, dimension, channels.len()].map(|n| (n as u64).to_le_bytes()).concat();
+        let worker = stream.write_all(&handshake).and_then(|_| read_u64s::<2>(&mut stream));
+
+        let mismatch = match worker {
+            Ok([bytes, worker_dimension]) => check_handshake(T::BYTES, dimension, bytes as usize, worker_dimension as usize),
+            Err(e) => Err(format!("broken handshake: {e}")),
+        };
+
+        if let Err(e) = mismatch {
+            write_log(
+                write_logs_to.clone(),
+                "master",
+                &format!("rejected a remote worker from {peer}: {e}"),
+            );
+            continue;
+        }
+
+        write_log(
+            write_logs_to.clone(),
+            "master",
+            &format!("remote worker {} connected from {peer}", channels.len()),
+        );
+
+        let (tx_to_main, rx_to_main) = mpsc::channel();
+        let (tx_from_main, rx_from_main) = mpsc::channel();
+        bridge(
+            stream,
+            rx_from_main,
+            tx_to_main,
+            encode_message_from_main::<T>,
+            decode_message_to_main::<T>,
+            "master",
+            write_logs_to.clone(),
+        )?;
+
+        channels.push(Channel::new(tx_from_main, rx_to_main));
+    }
+
+    Ok(channels)
+}
+
+// It returns when the master closes the connection.
+pub fn run_remote_worker(address: &str, config: &Config) -> io::Result<()> {
+    let started_at = Instant::now();
+
+    let stream = loop {
+        match TcpStream::connect(address) {
+            Ok(stream) => { break stream; },
+            Err(e) => {
+                if started_at.elapsed() > CONNECT_TIMEOUT {
+                    return Err(e);
+                }
+
+                // the master may not be listening yet
+                thread::sleep(Duration::from_millis(500));
+            },
+        }
+    };
+    stream.set_nodelay(true)?;
+
+    let worker_config = WorkerConfig::from_config(config);
+    let bytes = match worker_config.objective.precision() {
+        Precision::F32 => f32::BYTES,
+        Precision::F64 => f64::BYTES,
+    };
+    let dimension = resolve_dimension(&config.objective, config.dimension).map_err(
+        |e| io::Error::new(io::ErrorKind::InvalidInput, e)
+    )?;
+
+    let [master_bytes, master_dimension, worker_index] = read_u64s::<3>(&mut (&stream))?;
+    (&stream).write_all(&[bytes, dimension].map(|n| (n as u64).to_le_bytes()).concat())?;
+
+    // the master checks it, too, and closes the connection
+    check_handshake(master_bytes as usize, master_dimension as usize, bytes, dimension).map_err(
+        |e| io::Error::new(io::ErrorKind::InvalidData, e)
+    )?;
+
+    match worker_config.objective.precision() {
+        Precision::F32 => run_remote_event_loop::<f32>(stream, worker_config, worker_index as usize),
+        Precision::F64 => run_remote_event_loop::<f64>(stream, worker_config, worker_index as usize),
+    }
+}
+
+fn read_u64s<const N: usize>(stream: &mut impl Read) -> io::Result<[u64; N]> {
+    let mut result = [0; N];
+
+    for n in result.iter_mut() {
+        let mut bytes = [0; 8];
+        stream.read_exact(&mut bytes)?;
+        *n = u64::from_le_bytes(bytes);
+    }
+
+    Ok(result)
+}
+
+fn check_handshake(master_bytes: usize, master_dimension: usize, worker_bytes: usize, worker_dimension: usize) -> Result<(), String> {
+    let precision = |bytes: usize| match bytes {
+        4 => String::from("f32"),
+        8 => String::from("f64"),
+        _ => format!("{bytes}-byte floats"),
+    };
+
+    if (master_bytes, master_dimension) == (worker_bytes, worker_dimension) {
+        Ok(())
+    }
+
+    else {
+        Err(format!(
+            "the master optimizes {master_dimension} parameters in {}, but the worker {worker_dimension} parameters in {} (is it compiled with the same `config.rs`?)",
+            precision(master_bytes),
+            precision(worker_bytes),
+        ))
+    }
+}
+
+fn run_remote_event_loop<T: Float>(stream: TcpStream, worker_config: WorkerConfig, worker_index: usize) -> io::Result<()> {
+    let (tx_to_main, rx_to_main) = mpsc::channel();
+    let (tx_from_main, rx_from_main) = mpsc::channel();
+    bridge(
+        stream,
+        rx_to_main,
+        tx_from_main,
+        encode_message_to_main::<T>,
+        decode_message_from_main::<T>,
+        "remote worker",
+        worker_config.write_logs_to.clone(),
+    )?;
+
+    event_loop(tx_to_main, rx_from_main, worker_config, worker_index);
+    Ok(())
+}
+
+// Spawns 2 threads: one encodes messages from `outgoing` and writes them to `stream`,
+// and the other reads messages from `stream` and sends them to `incoming`.
+// When either side dies, the threads drop their end of the channels, so that the
+// owner of the other ends notices it.
+fn bridge<Out: Send + 'static, In: Send + 'static>(
+    stream: TcpStream,
+    outgoing: mpsc::Receiver<Out>,
+    incoming: mpsc::Sender<In>,
+    encode: fn(&Out, &mut Vec<u8>),
+    decode: fn(&mut Decoder) -> Result<In, DecodeError>,
+    owner: &'static str,
+    write_logs_to: Option<String>,
+) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    let write_half = stream.try_clone()?;
+
+    thread::spawn(move || {
+        let mut writer = BufWriter::new(write_half);
+        let mut buffer = vec![];
+
+        while let Ok(msg) = outgoing.recv() {
+            buffer.clear();
+            encode(&msg, &mut buffer);
+
+            let result = writer.write_all(&(buffer.len() as u32).to_le_bytes())
+                .and_then(|_| writer.write_all(&buffer))
+                .and_then(|_| writer.flush());
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        // so that the reader thread stops, too
+        let _ = writer.get_ref().shutdown(std::net::Shutdown::Both);
+    });
+
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut length = [0; 4];
+
+        loop {
+            if reader.read_exact(&mut length).is_err() {
+                break;
+            }
+
+            let mut payload = vec![0; u32::from_le_bytes(length) as usize];
+
+            if reader.read_exact(&mut payload).is_err() {
+                break;
+            }
+
+            match decode(&mut Decoder::new(&payload)) {
+                Ok(msg) => {
+                    if incoming.send(msg).is_err() {
+                        break;
+                    }
+                },
+                Err(e) => {
+                    write_log(
+                        write_logs_to.clone(),
+                        owner,
+                        &format!("invalid message from {peer}: {}", e.render_error()),
+                    );
+                    break;
+                },
+            }
+        }
+
+        write_log(
+            write_logs_to,
+            owner,
+            &format!("connection to {peer} is closed"),
+        );
+        let _ = reader.get_ref().shutdown(std::net::Shutdown::Both);
+    });
+
+    Ok(())
+}
+
+fn encode_message_from_main<T: Float>(msg: &MessageFromMain<T>, buffer: &mut Vec<u8>) {
+    match msg {
+        MessageFromMain::TryRandomParams { param_l2_norm, dimension, count, strategy, partition } => {
+            buffer.push(0);
+            encode_float(*param_l2_norm, buffer);
+            encode_usize(*dimension, buffer);
+            encode_usize(*count, buffer);
+            encode_strategy(strategy, buffer);
+            encode_usize(partition.index, buffer);
+            encode_usize(partition.total, buffer);
+            buffer.extend_from_slice(&partition.seed.to_le_bytes());
+        },
+        MessageFromMain::TryWithGradient {
+            state_id,
+            version,
+            curr_params,
+            prev_step,
+            step_moment,
+            step_size,
+            count,
+            scales,
+        } => {
+            buffer.push(1);
+            encode_usize(*state_id, buffer);
+            encode_usize(*version, buffer);
+            encode_floats(curr_params, buffer);
+
+            match prev_step {
+                Some(prev_step) => {
+                    buffer.push(1);
+                    encode_floats(prev_step, buffer);
+                },
+                None => { buffer.push(0); },
+            }
+
+            encode_float(*step_moment, buffer);
+            encode_float(*step_size, buffer);
+            encode_usize(*count, buffer);
+
+            match scales {
+                Some(scales) => {
+                    buffer.push(1);
+                    encode_floats(scales, buffer);
+                },
+                None => { buffer.push(0); },
+            }
+        },
+        MessageFromMain::HealthCheck => {
+            buffer.push(2);
+        },
+        MessageFromMain::Evaluate { index, parameters } => {
+            buffer.push(3);
+            encode_usize(*index, buffer);
+            encode_floats(parameters, buffer);
+        },
+    }
+}
+
+fn decode_message_from_main<T: Float>(decoder: &mut Decoder) -> Result<MessageFromMain<T>, DecodeError> {
+    match decoder.u8()? {
+        0 => Ok(MessageFromMain::TryRandomParams {
+            param_l2_norm: decoder.float()?,
+            dimension: decoder.usize()?,
+            count: decoder.usize()?,
+            strategy: decoder.strategy()?,
+            partition: Partition {
+                index: decoder.usize()?,
+                total: decoder.usize()?,
+                seed: decoder.u64()?,
+            },
+        }),
+        1 => Ok(MessageFromMain::TryWithGradient {
+            state_id: decoder.usize()?,
+            version: decoder.usize()?,
+            curr_params: decoder.floats()?.into(),
+            prev_step: match decoder.u8()? {
+                0 => None,
+                _ => Some(decoder.floats()?.into()),
+            },
+            step_moment: decoder.float()?,
+            step_size: decoder.float()?,
+            count: decoder.usize()?,
+            scales: match decoder.u8()? {
+                0 => None,
+                _ => Some(decoder.floats()?),
+            },
+        }),
+        2 => Ok(MessageFromMain::HealthCheck),
+        3 => Ok(MessageFromMain::Evaluate {
+            index: decoder.usize()?,
+            parameters: decoder.floats()?,
+        }),
+        tag => Err(DecodeError::InvalidTag(tag)),
+    }
+}
+
+fn encode_message_to_main<T: Float>(msg: &MessageToMain<T>, buffer: &mut Vec<u8>) {
+    match msg {
+        MessageToMain::RandomParamResult { best_params, best_loss, stats } => {
+            buffer.push(0);
+            encode_floats(best_params, buffer);
+            encode_float(*best_loss, buffer);
+            encode_stats(stats, buffer);
+        },
+        MessageToMain::WithGradientResult { state_id, version, best_loss, step, stats } => {
+            buffer.push(1);
+            encode_usize(*state_id, buffer);
+            encode_usize(*version, buffer);
+            encode_float(*best_loss, buffer);
+            encode_floats(step, buffer);
+            encode_stats(stats, buffer);
+        },
+        MessageToMain::WithGradientResultFailure { state_id, stats } => {
+            buffer.push(2);
+            encode_usize(*state_id, buffer);
+            encode_stats(stats, buffer);
+        },
+        MessageToMain::EvaluationError { reason, count } => {
+            buffer.push(3);
+            encode_usize(reason.len(), buffer);
+            buffer.extend_from_slice(reason.as_bytes());
+            encode_usize(*count, buffer);
+        },
+        MessageToMain::EvaluateResult { index, loss, stats } => {
+            buffer.push(4);
+            encode_usize(*index, buffer);
+            encode_float(*loss, buffer);
+            encode_stats(stats, buffer);
+        },
+        MessageToMain::SparseResult { state_id, version, indices, delta, best_loss, stats } => {
+            buffer.push(5);
+            encode_usize(*state_id, buffer);
+            encode_usize(*version, buffer);
+            encode_usizes(indices, buffer);
+            encode_floats(delta, buffer);
+            encode_float(*best_loss, buffer);
+            encode_stats(stats, buffer);
+        },
+    }
+}
+
+fn decode_message_to_main<T: Float>(decoder: &mut Decoder) -> Result<MessageToMain<T>, DecodeError> {
+    match decoder.u8()? {
+        0 => Ok(MessageToMain::RandomParamResult {
+            best_params: decoder.floats()?,
+            best_loss: decoder.float()?,
+            stats: decoder.stats()?,
+        }),
+        1 => Ok(MessageToMain::WithGradientResult {
+            state_id: decoder.usize()?,
+            version: decoder.usize()?,
+            best_loss: decoder.float()?,
+            step: decoder.floats()?,
+            stats: decoder.stats()?,
+        }),
+        2 => Ok(MessageToMain::WithGradientResultFailure {
+            state_id: decoder.usize()?,
+            stats: decoder.stats()?,
+        }),
+        3 => Ok(MessageToMain::EvaluationError {
+            reason: decoder.string()?,
+            count: decoder.usize()?,
+        }),
+        4 => Ok(MessageToMain::EvaluateResult {
+            index: decoder.usize()?,
+            loss: decoder.float()?,
+            stats: decoder.stats()?,
+        }),
+        5 => Ok(MessageToMain::SparseResult {
+            state_id: decoder.usize()?,
+            version: decoder.usize()?,
+            indices: decoder.usizes()?,
+            delta: decoder.floats()?,
+            best_loss: decoder.float()?,
+            stats: decoder.stats()?,
+        }),
+        tag => Err(DecodeError::InvalidTag(tag)),
+    }
+}
+
+fn encode_usize(n: usize, buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(n as u64).to_le_bytes());
+}
+
+fn encode_usizes(v: &[usize], buffer: &mut Vec<u8>) {
+    encode_usize(v.len(), buffer);
+
+    for n in v.iter() {
+        encode_usize(*n, buffer);
+    }
+}
+
+fn encode_float<T: Float>(n: T, buffer: &mut Vec<u8>) {
+    n.write_le_bytes(buffer);
+}
+
+fn encode_floats<T: Float>(v: &[T], buffer: &mut Vec<u8>) {
+    encode_usize(v.len(), buffer);
+
+    for n in v.iter() {
+        encode_float(*n, buffer);
+    }
+}
+
+fn encode_strategy(strategy: &InitStrategy, buffer: &mut Vec<u8>) {
+    match strategy {
+        InitStrategy::RandomL2Norm => {
+            buffer.push(0);
+        },
+        InitStrategy::Uniform { lower, upper } => {
+            buffer.push(1);
+            encode_float(*lower, buffer);
+            encode_float(*upper, buffer);
+        },
+        InitStrategy::Gaussian { scale } => {
+            buffer.push(2);
+            encode_float(*scale, buffer);
+        },
+        InitStrategy::LatinHypercube { lower, upper } => {
+            buffer.push(3);
+            encode_float(*lower, buffer);
+            encode_float(*upper, buffer);
+        },
+        InitStrategy::Halton { lower, upper } => {
+            buffer.push(4);
+            encode_float(*lower, buffer);
+            encode_float(*upper, buffer);
+        },
+    }
+}
+
+fn encode_stats(stats: &EvalStats, buffer: &mut Vec<u8>) {
+    encode_usize(stats.evaluations, buffer);
+    buffer.extend_from_slice(&(stats.eval_time.as_nanos() as u64).to_le_bytes());
+    encode_usize(stats.acceptances, buffer);
+    encode_usize(stats.failures, buffer);
+    encode_usize(stats.panics, buffer);
+    encode_usize(stats.timeouts, buffer);
+    encode_usize(stats.non_finite, buffer);
+}
+
+pub enum DecodeError {
+    UnexpectedEof,
+    InvalidTag(u8),
+    InvalidUtf8,
+}
+
+impl DecodeError {
+    pub fn render_error(&self) -> String {
+        match self {
+            DecodeError::UnexpectedEof => String::from("unexpected end of message"),
+            DecodeError::InvalidTag(tag) => format!("invalid tag: {tag}"),
+            DecodeError::InvalidUtf8 => String::from("invalid utf-8"),
+        }
+    }
+}
+
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, cursor: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        // `n` comes from the wire, so it may be as large as `u64::MAX`
+        let end = match self.cursor.checked_add(n) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => { return Err(DecodeError::UnexpectedEof); },
+        };
+
+        let result = &self.bytes[self.cursor..end];
+        self.cursor = end;
+
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        // `take` guarantees the length
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        Ok(self.u64()? as usize)
+    }
+
+    fn usizes(&mut self) -> Result<Vec<usize>, DecodeError> {
+        let length = self.usize()?;
+
+        // a broken length shouldn't allocate a huge buffer
+        if length > self.bytes.len() - self.cursor {
+            return Err(DecodeError::UnexpectedEof);
+        }
+
+        (0..length).map(|_| self.usize()).collect()
+    }
+
+    fn float<T: Float>(&mut self) -> Result<T, DecodeError> {
+        // `take` guarantees the length
+        Ok(T::from_le_bytes(self.take(T::BYTES)?))
+    }
+
+    fn floats<T: Float>(&mut self) -> Result<Vec<T>, DecodeError> {
+        let length = self.usize()?;
+
+        // a broken length shouldn't allocate a huge buffer
+        if length > self.bytes.len() - self.cursor {
+            return Err(DecodeError::UnexpectedEof);
+        }
+
+        (0..length).map(|_| self.float()).collect()
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let length = self.usize()?;
+        let bytes = self.take(length)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn strategy(&mut self) -> Result<InitStrategy, DecodeError> {
+        match self.u8()? {
+            0 => Ok(InitStrategy::RandomL2Norm),
+            1 => Ok(InitStrategy::Uniform { lower: self.float()?, upper: self.float()? }),
+            2 => Ok(InitStrategy::Gaussian { scale: self.float()? }),
+            3 => Ok(InitStrategy::LatinHypercube { lower: self.float()?, upper: self.float()? }),
+            4 => Ok(InitStrategy::Halton { lower: self.float()?, upper: self.float()? }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+
+    fn stats(&mut self) -> Result<EvalStats, DecodeError> {
+        Ok(EvalStats {
+            evaluations: self.usize()?,
+            eval_time: Duration::from_nanos(self.u64()?),
+            acceptances: self.usize()?,
+            failures: self.usize()?,
+            panics: self.usize()?,
+            timeouts: self.usize()?,
+            non_finite: self.usize()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::default_config;
+    use crate::master;
+    use crate::objective::Objective;
+    use crate::samples::benchmarks::Benchmark;
+    use std::sync::Arc;
+
+    fn stats() -> EvalStats {
+        EvalStats {
+            evaluations: 1,
+            eval_time: Duration::from_nanos(2),
+            acceptances: 3,
+            failures: 4,
+            panics: 5,
+            timeouts: 6,
+            non_finite: 7,
+        }
+    }
+
+    // decoding and encoding again gives the same bytes, and consumes every byte
+    fn round_trip<M>(msg: &M, encode: fn(&M, &mut Vec<u8>), decode: fn(&mut Decoder) -> Result<M, DecodeError>) {
+        let mut bytes = vec![];
+        encode(msg, &mut bytes);
+
+        let mut decoder = Decoder::new(&bytes);
+        let decoded = decode(&mut decoder).map_err(|e| e.render_error()).unwrap();
+        assert_eq!(decoder.cursor, bytes.len());
+
+        let mut encoded_again = vec![];
+        encode(&decoded, &mut encoded_again);
+        assert_eq!(bytes, encoded_again);
+
+        // a truncated message is an error, not a panic
+        for length in 0..bytes.len() {
+            assert!(decode(&mut Decoder::new(&bytes[..length])).is_err());
+        }
+    }
+
+    fn messages_from_main<T: Float>() -> Vec<MessageFromMain<T>> {
+        let params: Arc<[T]> = vec![T::from_f64(0.5), T::from_f64(-1.25)].into();
+
+        vec![
+            MessageFromMain::TryRandomParams {
+                param_l2_norm: T::from_f64(1.5),
+                dimension: 2,
+                count: 64,
+                strategy: InitStrategy::Halton { lower: -1.0, upper: 2.0 },
+                partition: Partition { index: 1, total: 4, seed: u64::MAX },
+            },
+            MessageFromMain::TryRandomParams {
+                param_l2_norm: T::from_f64(1.5),
+                dimension: 2,
+                count: 64,
+                strategy: InitStrategy::Gaussian { scale: 0.25 },
+                partition: Partition { index: 0, total: 1, seed: 0 },
+            },
+            MessageFromMain::TryWithGradient {
+                state_id: 1,
+                version: 7,
+                curr_params: params.clone(),
+                prev_step: Some(params.clone()),
+                step_moment: T::from_f64(0.65),
+                step_size: T::from_f64(0.5),
+                count: 32,
+                scales: Some(vec![T::ONE, T::from_f64(2.0)]),
+            },
+            MessageFromMain::TryWithGradient {
+                state_id: 0,
+                version: 8,
+                curr_params: params,
+                prev_step: None,
+                step_moment: T::ZERO,
+                step_size: T::ONE,
+                count: 1,
+                scales: None,
+            },
+            MessageFromMain::Evaluate { index: 3, parameters: vec![T::from_f64(3.0); 5] },
+            MessageFromMain::HealthCheck,
+        ]
+    }
+
+    fn messages_to_main<T: Float>() -> Vec<MessageToMain<T>> {
+        vec![
+            MessageToMain::RandomParamResult {
+                best_params: vec![T::from_f64(0.5), T::from_f64(-1.25)],
+                best_loss: T::INFINITY,
+                stats: stats(),
+            },
+            MessageToMain::WithGradientResult {
+                state_id: 1,
+                version: 7,
+                best_loss: T::from_f64(0.125),
+                step: vec![T::from_f64(0.25); 3],
+                stats: stats(),
+            },
+            MessageToMain::WithGradientResultFailure { state_id: 2, stats: stats() },
+            MessageToMain::SparseResult {
+                state_id: 0,
+                version: 9,
+                indices: vec![1, 5, 8],
+                delta: vec![T::ONE, T::ZERO, T::from_f64(-0.5)],
+                best_loss: T::from_f64(2.0),
+                stats: stats(),
+            },
+            MessageToMain::EvaluateResult { index: 4, loss: T::from_f64(1.0), stats: EvalStats::default() },
+            MessageToMain::EvaluationError { reason: String::from("`f` panicked: é"), count: 3 },
+        ]
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for msg in messages_from_main::<f32>().iter() {
+            round_trip(msg, encode_message_from_main, decode_message_from_main);
+        }
+
+        for msg in messages_from_main::<f64>().iter() {
+            round_trip(msg, encode_message_from_main, decode_message_from_main);
+        }
+
+        for msg in messages_to_main::<f32>().iter() {
+            round_trip(msg, encode_message_to_main, decode_message_to_main);
+        }
+
+        for msg in messages_to_main::<f64>().iter() {
+            round_trip(msg, encode_message_to_main, decode_message_to_main);
+        }
+    }
+
+    #[test]
+    fn broken_messages() {
+        assert!(matches!(decode_message_to_main::<f64>(&mut Decoder::new(&[9])), Err(DecodeError::InvalidTag(9))));
+
+        // a length of `u64::MAX` must not overflow the cursor
+        let mut bytes = vec![3];
+        encode_usize(usize::MAX, &mut bytes);
+        assert!(matches!(decode_message_to_main::<f64>(&mut Decoder::new(&bytes)), Err(DecodeError::UnexpectedEof)));
+
+        let mut bytes = vec![3];
+        encode_usize(2, &mut bytes);
+        bytes.extend_from_slice(&[0xff, 0xfe]);
+        encode_usize(1, &mut bytes);
+        assert!(matches!(decode_message_to_main::<f64>(&mut Decoder::new(&bytes)), Err(DecodeError::InvalidUtf8)));
+    }
+
+    // an unused port: it's free again when the listener is dropped
+    fn unused_address() -> String {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+    }
+
+    fn remote_config(address: &str, num_workers: usize) -> Config {
+        let mut config = default_config();
+        config.objective = Objective::Benchmark(Benchmark::Sphere);
+        config.dimension = Some(8);
+        config.num_workers = num_workers;
+        config.iter_per_worker = 32;
+        config.visualize = false;
+        config.write_logs_to = None;
+        config.max_evaluations = Some(2_000);
+        config.listen_for_workers = Some(address.to_string());
+        config.seed = Some(0);
+
+        config
+    }
+
+    #[test]
+    fn loopback_workers() {
+        let address = unused_address();
+        let config = || remote_config(&address, 2);
+
+        let workers = (0..2).map(|_| {
+            let (address, config) = (address.clone(), config());
+            thread::spawn(move || run_remote_worker(&address, &config))
+        }).collect::<Vec<_>>();
+
+        let summary = master::run(config());
+
+        assert!(summary.evaluations >= 2_000);
+        assert!(summary.best_loss.is_finite());
+        assert_eq!(summary.best_params.len(), 8);
+
+        // the workers return when the master closes the connections
+        for worker in workers {
+            assert!(worker.join().unwrap().is_ok());
+        }
+    }
+
+    #[test]
+    fn handshake_rejects_mismatches() {
+        let address = unused_address();
+
+        // it waits for a single worker, in `f64` with 8 parameters
+        let master = {
+            let address = address.clone();
+            thread::spawn(move || init_remote_channels::<f64>(&address, 1, 8, &None).map(|channels| channels.len()))
+        };
+
+        let mut f32_worker = remote_config(&address, 1);
+        f32_worker.objective = Objective::Native(|parameters| parameters.iter().sum());
+
+        let mut wider_worker = remote_config(&address, 1);
+        wider_worker.dimension = Some(9);
+
+        for (config, expected) in [(f32_worker, "8 parameters in f64, but the worker 8 parameters in f32"), (wider_worker, "but the worker 9 parameters in f64")] {
+            match run_remote_worker(&address, &config) {
+                Err(e) => {
+                    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                    assert!(e.to_string().contains(expected), "{e}");
+                },
+                Ok(()) => panic!("the handshake has to fail"),
+            }
+        }
+
+        // the rejected workers are not counted, and a matching worker still gets index 0
+        let worker = thread::spawn(move || run_remote_worker(&address, &remote_config(&address, 1)));
+        assert_eq!(master.join().unwrap().unwrap(), 1);
+
+        // the channel is dropped, so the worker returns
+        assert!(worker.join().unwrap().is_ok());
+    }
+
+    // the worker of `separate_process_worker`: `cargo test` skips it
+    #[test]
+    #[ignore]
+    fn worker_process() {
+        if let Ok(address) = std::env::var("NONLINEAR_OPT_TEST_MASTER") {
+            run_remote_worker(&address, &remote_config(&address, 1)).unwrap();
+        }
+    }
+
+    // a remote worker in another process: it has its own statics, and nothing is shared but the stream
+    #[test]
+    fn separate_process_worker() {
+        let address = unused_address();
+        let mut worker = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["net::tests::worker_process", "--exact", "--ignored", "--quiet"])
+            .env("NONLINEAR_OPT_TEST_MASTER", &address)
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let summary = master::run(remote_config(&address, 1));
+
+        assert!(summary.evaluations >= 2_000);
+        assert!(summary.best_loss.is_finite());
+        assert!(worker.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,372 @@
+
+use crate::eval::EvalError;
+use crate::expr::{compile_cached, Expr};
+use crate::float::{Float, Precision};
+use crate::plugin::Plugin;
+use crate::samples::benchmarks::Benchmark;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+// The first call to a freshly spawned process gets this much more time than `eval_timeout`,
+// because it includes the startup of the process (e.g. a python interpreter).
+const STARTUP_GRACE: Duration = Duration::from_secs(10);
+
+// What the workers optimize. Set `Config.objective` to choose one, or pick one on the command line
+// with `nonlinear_opt run` (see `from_args`).
+// It also decides whether the optimizer works in `f32` or `f64` (see `precision`).
+#[derive(Clone)]
+pub enum Objective {
+    // a rust function, usually `config::f`
+    Native(fn(&[f32]) -> f32),
+
+    // a rust function in `f64` (a non-capturing closure works, too)
+    NativeF64(fn(&[f64]) -> f64),
+
+    // `Native`, with a function that returns the number of parameters (e.g. `samples::text::dimension`)
+    // the master calls `dimension` once, before it starts the workers
+    NativeWithDimension {
+        f: fn(&[f32]) -> f32,
+        dimension: fn() -> usize,
+    },
+
+    // Each worker spawns `command args...` once and talks to it over stdin/stdout.
+    // The parameters are printed in `f64`.
+    //
+    // protocol (one line per evaluation)
+    //   worker -> command: parameters, separated by a single space, terminated by '\n'
+    //   command -> worker: loss, terminated by '\n'
+    //
+    // Make sure that the command flushes stdout after each line. If the command dies or
+    // prints something that's not a number, the evaluation fails (like a panic of `f`)
+    // and the worker spawns a new process for the next evaluation.
+    //
+    // example (python)
+    //   import sys
+    //   for line in sys.stdin:
+    //       x = [float(n) for n in line.split()]
+    //       print(sum((n - 1) ** 2 for n in x), flush=True)
+    External {
+        command: String,
+        args: Vec<String>,
+    },
+
+    // a shared library that exports `nlopt_dimension` and `nlopt_evaluate` (see `plugin.rs`)
+    // if it also exports `nlopt_gradient`, the first step of each state follows the gradient
+    Plugin {
+        path: String,
+    },
+
+    // an expression like `sum((x[i] - 1)^2) + 0.1 * x[0] * x[1]` (see `expr.rs` for the syntax)
+    Expression(String),
+
+    // a standard test function with a known optimum (see `samples/benchmarks.rs`)
+    // e.g. `Objective::Benchmark(Benchmark::Rosenbrock)`
+    Benchmark(Benchmark),
+}
+
+impl Objective {
+    // The optimizer works in this type. Only `Native` and `NativeWithDimension` are `f32`: the others compute in `f64` anyway.
+    pub fn precision(&self) -> Precision {
+        match self {
+            Objective::Native(_) | Objective::NativeWithDimension { .. } => Precision::F32,
+            Objective::NativeF64(_)
+            | Objective::External { .. }
+            | Objective::Plugin { .. }
+            | Objective::Expression(_)
+            | Objective::Benchmark(_) => Precision::F64,
+        }
+    }
+
+    // whether `LossFunction::call` handles timeouts by itself
+    // if not, the evaluator runs it on a watchdog thread
+    pub fn handles_timeout(&self) -> bool {
+        match self {
+            Objective::Native(_) => false,
+            Objective::NativeF64(_) => false,
+            Objective::NativeWithDimension { .. } => false,
+            Objective::External { .. } => true,
+            Objective::Plugin { .. } => false,
+            Objective::Expression(_) => false,
+            Objective::Benchmark(_) => false,
+        }
+    }
+
+    // None if the objective doesn't know its dimension
+    // it fails if the objective cannot be loaded (or compiled), so the master calls it before starting workers
+    pub fn dimension(&self) -> Result<Option<usize>, EvalError> {
+        match self {
+            Objective::Native(_)
+            | Objective::NativeF64(_)
+            | Objective::External { .. }
+            | Objective::Benchmark(_) => Ok(None),
+            Objective::Expression(source) => match compile_cached(source) {
+                Ok(_) => Ok(None),
+                Err(e) => Err(EvalError::Failed(e.render_error(source))),
+            },
+            Objective::NativeWithDimension { dimension, .. } => Ok(Some(dimension())),
+            Objective::Plugin { path } => Ok(Some(Plugin::load(path).map_err(EvalError::Failed)?.dimension())),
+        }
+    }
+
+    // `nonlinear_opt run <objective>`
+    //   exec <command> [<args>...]: `External`
+    //   plugin <path>: `Plugin`
+    //   expr <expression>: `Expression`
+    //   benchmark <name>: `Benchmark` (see `Benchmark::from_name`)
+    //   text: `samples::text`
+    //   graph: `samples::graph`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let args = args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>();
+
+        match args.as_slice() {
+            ["exec", command, args @ ..] => Ok(Objective::External {
+                command: command.to_string(),
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+            }),
+            ["plugin", path] => Ok(Objective::Plugin { path: path.to_string() }),
+            ["expr", source] => Ok(Objective::Expression(source.to_string())),
+            ["benchmark", name] => match Benchmark::from_name(name) {
+                Some(benchmark) => Ok(Objective::Benchmark(benchmark)),
+                None => Err(format!(
+                    "`{name}` is not a benchmark: {}",
+                    Benchmark::ALL.iter().map(|benchmark| benchmark.name()).collect::<Vec<_>>().join(", "),
+                )),
+            },
+            ["text"] => Ok(Objective::NativeWithDimension {
+                f: crate::samples::text::f,
+                dimension: crate::samples::text::dimension,
+            }),
+            ["graph"] => Ok(Objective::NativeWithDimension {
+                f: crate::samples::graph::f,
+                dimension: crate::samples::graph::dimension,
+            }),
+            _ => Err(format!("`{}` is not an objective", args.join(" "))),
+        }
+    }
+
+    // whether `dimension` returns `Some`, without loading anything
+    pub fn knows_dimension(&self) -> bool {
+        matches!(self, Objective::NativeWithDimension { .. } | Objective::Plugin { .. })
+    }
+
+    // it's called by each worker
+    pub fn instantiate(&self) -> Result<LossFunction, EvalError> {
+        match self {
+            Objective::Native(f) => Ok(LossFunction::Native(*f)),
+            Objective::NativeF64(f) => Ok(LossFunction::NativeF64(*f)),
+            Objective::NativeWithDimension { f, .. } => Ok(LossFunction::Native(*f)),
+            Objective::External { command, args } => Ok(LossFunction::External(ExternalProcess::spawn(command, args)?)),
+            Objective::Plugin { path } => Ok(LossFunction::Plugin(Plugin::load(path).map_err(EvalError::Failed)?)),
+            Objective::Expression(source) => match compile_cached(source) {
+                Ok(expr) => Ok(LossFunction::Expression(expr)),
+                Err(e) => Err(EvalError::Failed(e.render_error(source))),
+            },
+            Objective::Benchmark(benchmark) => Ok(LossFunction::Benchmark(*benchmark)),
+        }
+    }
+}
+
+// An instance of `Objective`, owned by a worker.
+// If the parameters are not in the type of the objective, they're converted.
+pub enum LossFunction {
+    Native(fn(&[f32]) -> f32),
+    NativeF64(fn(&[f64]) -> f64),
+    External(ExternalProcess),
+    Plugin(Plugin),
+    Expression(Arc<Expr>),
+    Benchmark(Benchmark),
+}
+
+impl LossFunction {
+    // `timeout` is ignored if `Objective::handles_timeout` is false
+    pub fn call<T: Float>(&mut self, parameters: &[T], timeout: Option<Duration>) -> Result<T, EvalError> {
+        match self {
+            LossFunction::Native(f) => Ok(T::from_f64(f(&T::to_f32_slice(parameters)) as f64)),
+            LossFunction::NativeF64(f) => Ok(T::from_f64(f(&T::to_f64_slice(parameters)))),
+            LossFunction::External(process) => process.call(parameters, timeout),
+            LossFunction::Plugin(plugin) => plugin.evaluate(parameters).map_err(EvalError::Failed),
+            LossFunction::Expression(expr) => expr.eval(&T::to_f64_slice(parameters)).map(T::from_f64).map_err(EvalError::Failed),
+            LossFunction::Benchmark(benchmark) => Ok(T::from_f64(benchmark.f(&T::to_f64_slice(parameters)))),
+        }
+    }
+
+    // None if the objective doesn't provide a gradient
+    pub fn gradient<T: Float>(&mut self, parameters: &[T]) -> Option<Result<Vec<T>, EvalError>> {
+        match self {
+            LossFunction::Native(_)
+            | LossFunction::NativeF64(_)
+            | LossFunction::External(_)
+            | LossFunction::Expression(_)
+            | LossFunction::Benchmark(_) => None,
+            LossFunction::Plugin(plugin) => plugin.gradient(parameters).map(|g| g.map_err(EvalError::Failed)),
+        }
+    }
+}
+
+pub struct ExternalProcess {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+
+    // lines of stdout, read by another thread, so that reading a line can time out
+    stdout: mpsc::Receiver<String>,
+
+    // whether the process has answered at least once
+    started: bool,
+}
+
+impl ExternalProcess {
+    pub fn spawn(command: &str, args: &[String]) -> Result<Self, EvalError> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| EvalError::Failed(format!("failed to spawn `{command}`: {e}")))?;
+
+        // `Stdio::piped()` guarantees that these are `Some`
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if tx.send(line).is_err() {
+                            break;
+                        }
+                    },
+                    Err(_) => { break; },
+                }
+            }
+        });
+
+        Ok(ExternalProcess {
+            command: command.to_string(),
+            child,
+            stdin,
+            stdout: rx,
+            started: false,
+        })
+    }
+
+    pub fn call<T: Float>(&mut self, parameters: &[T], timeout: Option<Duration>) -> Result<T, EvalError> {
+        let line = parameters.iter().map(|p| p.to_f64().to_string()).collect::<Vec<_>>().join(" ");
+
+        if let Err(e) = writeln!(self.stdin, "{line}").and_then(|_| self.stdin.flush()) {
+            return Err(EvalError::Failed(format!("failed to write to `{}`: {e}", self.command)));
+        }
+
+        let response = match timeout {
+            Some(timeout) => match self.stdout.recv_timeout(if self.started { timeout } else { timeout + STARTUP_GRACE }) {
+                Ok(response) => response,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(EvalError::TimedOut(timeout));
+                },
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(EvalError::Failed(format!("`{}` closed its stdout", self.command)));
+                },
+            },
+            None => match self.stdout.recv() {
+                Ok(response) => response,
+                Err(_) => {
+                    return Err(EvalError::Failed(format!("`{}` closed its stdout", self.command)));
+                },
+            },
+        };
+
+        self.started = true;
+
+        response.trim().parse::<f64>().map(T::from_f64).map_err(
+            |_| EvalError::Failed(format!("`{}` printed `{response}`, which is not a number", self.command))
+        )
+    }
+}
+
+impl Drop for ExternalProcess {
+    fn drop(&mut self) {
+        // the process may have exited already
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Evaluator;
+
+    fn shell(script: &str) -> ExternalProcess {
+        ExternalProcess::spawn("sh", &[String::from("-c"), script.to_string()]).map_err(|e| e.render_error()).unwrap()
+    }
+
+    #[test]
+    fn external_process() {
+        // it answers the second parameter
+        let mut process = shell("while read a b; do echo \"$b\"; done");
+        assert_eq!(process.call(&[1.0f64, 2.5], Some(Duration::from_secs(5))).map_err(|e| e.render_error()), Ok(2.5));
+        assert_eq!(process.call(&[1.0f32, -0.5], None).map_err(|e| e.render_error()), Ok(-0.5));
+    }
+
+    #[test]
+    fn external_process_errors() {
+        let mut malformed = shell("while read line; do echo hello; done");
+        assert!(matches!(malformed.call(&[1.0f64], None), Err(EvalError::Failed(_))));
+
+        let mut dead = shell("read line; exit 0");
+        assert!(matches!(dead.call(&[1.0f64], None), Err(EvalError::Failed(_))));
+
+        // the first call gets `STARTUP_GRACE`, so the timeout applies from the second call
+        let mut slow = shell("read line; echo 1; while read line; do sleep 5; echo 1; done");
+        assert_eq!(slow.call(&[1.0f64], Some(Duration::from_millis(100))).ok(), Some(1.0));
+        assert!(matches!(slow.call(&[1.0f64], Some(Duration::from_millis(100))), Err(EvalError::TimedOut(_))));
+    }
+
+    #[test]
+    fn objectives_from_args() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+
+        match Objective::from_args(&args("exec python3 ./objective.py -v")) {
+            Ok(Objective::External { command, args }) => {
+                assert_eq!(command, "python3");
+                assert_eq!(args, vec!["./objective.py", "-v"]);
+            },
+            _ => panic!("expected an external objective"),
+        }
+
+        assert!(matches!(Objective::from_args(&args("exec ls")), Ok(Objective::External { .. })));
+        assert!(matches!(Objective::from_args(&args("plugin ./libf.so")), Ok(Objective::Plugin { path }) if path == "./libf.so"));
+        assert!(matches!(Objective::from_args(&[String::from("expr"), String::from("sum(x[i]^2)")]), Ok(Objective::Expression(source)) if source == "sum(x[i]^2)"));
+
+        let text = Objective::from_args(&args("text")).unwrap();
+        assert!(text.knows_dimension());
+        assert_eq!(text.precision(), Precision::F32);
+        assert!(Objective::from_args(&args("graph")).unwrap().knows_dimension());
+        assert!(!Objective::from_args(&args("expr x[0]")).unwrap().knows_dimension());
+
+        assert!(matches!(Objective::from_args(&args("benchmark Levy")), Ok(Objective::Benchmark(Benchmark::Levy))));
+        assert!(matches!(Objective::from_args(&args("benchmark levi")), Err(e) if e.contains("sphere, rosenbrock")));
+
+        for source in ["", "exec", "plugin", "plugin a b", "expr", "text 3", "sphere"] {
+            assert!(Objective::from_args(&args(source)).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn external_non_finite_loss() {
+        let objective = Objective::External {
+            command: String::from("sh"),
+            args: vec![String::from("-c"), String::from("while read line; do echo nan; done")],
+        };
+        let mut evaluator = Evaluator::new(objective, None, 1e20f64);
+
+        assert_eq!(evaluator.eval(&[1.0, 2.0]), None);
+        assert_eq!(evaluator.take_stats().non_finite, 1);
+        assert!(matches!(evaluator.take_errors(), Some((EvalError::NonFinite { .. }, 1))));
+    }
+}
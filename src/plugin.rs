@@ -0,0 +1,220 @@
+// An objective in a shared library (`.so`, `.dylib` or `.dll`), loaded at runtime.
+// It lets one binary of this crate optimize many objectives without recompiling.
+//
+// The library has to export these symbols with the C ABI. Parameters are always `f64`,
+// so the optimizer runs in `f64`, too.
+//
+// ```c
+// // the number of parameters
+// size_t nlopt_dimension(void);
+//
+// // returns the loss
+// double nlopt_evaluate(const double* parameters, size_t length);
+//
+// // (optional) writes the gradient of the loss to `gradient`, which has `length` elements
+// // returns 0 on success
+// int nlopt_gradient(const double* parameters, size_t length, double* gradient);
+// ```
+//
+// example (rust, `crate-type = ["cdylib"]`)
+//   #[no_mangle]
+//   pub extern "C" fn nlopt_dimension() -> usize { 32 }
+//
+//   #[no_mangle]
+//   pub unsafe extern "C" fn nlopt_evaluate(parameters: *const f64, length: usize) -> f64 {
+//       std::slice::from_raw_parts(parameters, length).iter().map(|p| (p - 1.0) * (p - 1.0)).sum()
+//   }
+
+use crate::float::Float;
+use libloading::Library;
+
+type DimensionFn = unsafe extern "C" fn() -> usize;
+type EvaluateFn = unsafe extern "C" fn(*const f64, usize) -> f64;
+type GradientFn = unsafe extern "C" fn(*const f64, usize, *mut f64) -> i32;
+
+pub struct Plugin {
+    path: String,
+    dimension: usize,
+    evaluate: EvaluateFn,
+    gradient: Option<GradientFn>,
+
+    // parameters, converted to `f64`
+    buffer: Vec<f64>,
+
+    // the function pointers above are valid as long as this is alive
+    _library: Library,
+}
+
+impl Plugin {
+    pub fn load(path: &str) -> Result<Self, String> {
+        // SAFETY: loading a library runs its initializers. We have to trust the library.
+        let library = unsafe { Library::new(path) }.map_err(|e| format!("failed to load `{path}`: {e}"))?;
+
+        // SAFETY: the signatures are defined by the ABI above
+        let (dimension, evaluate, gradient) = unsafe {
+            let dimension = *library.get::<DimensionFn>(b"nlopt_dimension\0").map_err(
+                |e| format!("`{path}` doesn't export `nlopt_dimension`: {e}")
+            )?;
+            let evaluate = *library.get::<EvaluateFn>(b"nlopt_evaluate\0").map_err(
+                |e| format!("`{path}` doesn't export `nlopt_evaluate`: {e}")
+            )?;
+            let gradient = library.get::<GradientFn>(b"nlopt_gradient\0").ok().map(|f| *f);
+
+            (dimension(), evaluate, gradient)
+        };
+
+        Ok(Plugin {
+            path: path.to_string(),
+            dimension,
+            evaluate,
+            gradient,
+            buffer: vec![0.0; dimension],
+            _library: library,
+        })
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn evaluate<T: Float>(&mut self, parameters: &[T]) -> Result<T, String> {
+        self.fill_buffer(parameters)?;
+
+        // SAFETY: `buffer` has `dimension` elements
+        let loss = unsafe { (self.evaluate)(self.buffer.as_ptr(), self.buffer.len()) };
+
+        Ok(T::from_f64(loss))
+    }
+
+    // None if the plugin doesn't export `nlopt_gradient`
+    pub fn gradient<T: Float>(&mut self, parameters: &[T]) -> Option<Result<Vec<T>, String>> {
+        let gradient_fn = self.gradient?;
+
+        if let Err(e) = self.fill_buffer(parameters) {
+            return Some(Err(e));
+        }
+
+        let mut gradient = vec![0.0f64; self.dimension];
+
+        // SAFETY: `buffer` and `gradient` have `dimension` elements
+        let status = unsafe { gradient_fn(self.buffer.as_ptr(), self.buffer.len(), gradient.as_mut_ptr()) };
+
+        if status != 0 {
+            return Some(Err(format!("`nlopt_gradient` of `{}` returned {status}", self.path)));
+        }
+
+        Some(Ok(gradient.into_iter().map(T::from_f64).collect()))
+    }
+
+    fn fill_buffer<T: Float>(&mut self, parameters: &[T]) -> Result<(), String> {
+        if parameters.len() != self.dimension {
+            return Err(format!(
+                "`{}` expects {} parameters, but got {}",
+                self.path,
+                self.dimension,
+                parameters.len(),
+            ));
+        }
+
+        for (b, p) in self.buffer.iter_mut().zip(parameters.iter()) {
+            *b = p.to_f64();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::{remove_file, temp_path, write_string, WriteMode};
+    use crate::objective::Objective;
+    use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+    use std::process::Command;
+
+    // a plugin with a gradient: sum((x[i] - 1)^2), in 3 dimensions
+    const FIXTURE: &str = r#"
+        #[no_mangle]
+        pub extern "C" fn nlopt_dimension() -> usize { 3 }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn nlopt_evaluate(parameters: *const f64, length: usize) -> f64 {
+            std::slice::from_raw_parts(parameters, length).iter().map(|p| (p - 1.0) * (p - 1.0)).sum()
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn nlopt_gradient(parameters: *const f64, length: usize, gradient: *mut f64) -> i32 {
+            let parameters = std::slice::from_raw_parts(parameters, length);
+            let gradient = std::slice::from_raw_parts_mut(gradient, length);
+
+            for (g, p) in gradient.iter_mut().zip(parameters.iter()) {
+                *g = 2.0 * (p - 1.0);
+            }
+
+            0
+        }
+    "#;
+
+    // it doesn't export `nlopt_evaluate`
+    const FIXTURE_WITHOUT_EVALUATE: &str = r#"
+        #[no_mangle]
+        pub extern "C" fn nlopt_dimension() -> usize { 3 }
+    "#;
+
+    // compiles `source` to a cdylib, and returns its path
+    fn build_fixture(name: &str, source: &str) -> String {
+        let source_path = temp_path(&format!("{name}.rs"));
+        let library_path = temp_path(&format!("{DLL_PREFIX}{name}{DLL_SUFFIX}"));
+        write_string(&source_path, source, WriteMode::CreateOrTruncate).unwrap();
+
+        let status = Command::new(std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc")))
+            .args(["--crate-type", "cdylib", "--edition", "2021", "-o", &library_path, &source_path])
+            .status()
+            .unwrap();
+
+        remove_file(&source_path).unwrap();
+        assert!(status.success(), "cannot compile the fixture `{name}`");
+
+        library_path
+    }
+
+    #[test]
+    fn load_and_call() {
+        let path = build_fixture("plugin_fixture", FIXTURE);
+        let mut plugin = Plugin::load(&path).unwrap();
+
+        assert_eq!(plugin.dimension(), 3);
+        assert_eq!(plugin.evaluate(&[1.0f64, 2.0, 3.0]), Ok(5.0));
+        assert_eq!(plugin.evaluate(&[1.0f32, 1.0, 0.0]), Ok(1.0));
+        assert_eq!(plugin.gradient(&[1.0f64, 2.0, 3.0]), Some(Ok(vec![0.0, 2.0, 4.0])));
+
+        // the buffer has `dimension` elements
+        assert!(plugin.evaluate(&[1.0f64, 2.0]).unwrap_err().contains("expects 3 parameters, but got 2"));
+
+        // the master asks the objective for its dimension
+        let objective = Objective::Plugin { path: path.clone() };
+        assert_eq!(objective.dimension().map_err(|e| e.render_error()), Ok(Some(3)));
+
+        drop(plugin);
+        remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_errors() {
+        let path = temp_path("no_such_library.so");
+
+        match Plugin::load(&path) {
+            Err(e) => { assert!(e.starts_with(&format!("failed to load `{path}`")), "{e}"); },
+            Ok(_) => panic!("`{path}` doesn't exist"),
+        }
+
+        let path = build_fixture("plugin_fixture_without_evaluate", FIXTURE_WITHOUT_EVALUATE);
+
+        match Plugin::load(&path) {
+            Err(e) => { assert!(e.contains("doesn't export `nlopt_evaluate`"), "{e}"); },
+            Ok(_) => panic!("`nlopt_evaluate` is missing"),
+        }
+
+        remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+// Parameter vectors in a file, for `Config.initial_points`.
+//
+// The extension of the file decides the format.
+// - `.csv`: a vector per line, separated by commas. Empty lines and lines that start with `#` are ignored.
+// - `.json`: an array of vectors (`[[0.1, 0.2], [0.3, 0.4]]`), or a vector (`[0.1, 0.2]`)
+// - anything else: binary. `f64`s in little endian, and the vectors are concatenated,
+//   so the size of the file has to be a multiple of `8 * dimension`.
+
+use crate::files::{extension, read_bytes, read_string, FileError};
+
+pub enum PointsError {
+    File(FileError),
+    Syntax {
+        path: String,
+
+        // 1-based line number of a csv file, or 0-based byte offset of a json file
+        position: String,
+        message: String,
+    },
+    DimensionMismatch {
+        path: String,
+
+        // 0-based index of the vector
+        index: usize,
+        expected: usize,
+        got: usize,
+    },
+    Empty {
+        path: String,
+    },
+}
+
+impl PointsError {
+    pub fn render_error(&self) -> String {
+        match self {
+            PointsError::File(e) => e.render_error(),
+            PointsError::Syntax { path, position, message } => format!("`{path}`, {position}: {message}"),
+            PointsError::DimensionMismatch { path, index, expected, got } => format!(
+                "vector {index} of `{path}` has {got} parameters, but the objective has {expected}"
+            ),
+            PointsError::Empty { path } => format!("`{path}` doesn't have any vector"),
+        }
+    }
+}
+
+// It fails if any vector in the file doesn't have `dimension` elements.
+pub fn load_points(path: &str, dimension: usize) -> Result<Vec<Vec<f64>>, PointsError> {
+    let points = match extension(path).map_err(PointsError::File)?.as_deref() {
+        Some("csv") => parse_csv(path, &read_string(path).map_err(PointsError::File)?)?,
+        Some("json") => parse_json(path, &read_string(path).map_err(PointsError::File)?)?,
+        _ => parse_binary(path, &read_bytes(path).map_err(PointsError::File)?, dimension)?,
+    };
+
+    if points.is_empty() {
+        return Err(PointsError::Empty { path: path.to_string() });
+    }
+
+    for (index, point) in points.iter().enumerate() {
+        if point.len() != dimension {
+            return Err(PointsError::DimensionMismatch {
+                path: path.to_string(),
+                index,
+                expected: dimension,
+                got: point.len(),
+            });
+        }
+    }
+
+    Ok(points)
+}
+
+fn parse_csv(path: &str, s: &str) -> Result<Vec<Vec<f64>>, PointsError> {
+    let mut points = vec![];
+
+    for (index, line) in s.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let point = line.split(',').map(|n| n.trim().parse::<f64>().map_err(
+            |_| PointsError::Syntax {
+                path: path.to_string(),
+                position: format!("line {}", index + 1),
+                message: format!("`{}` is not a number", n.trim()),
+            }
+        )).collect::<Result<Vec<_>, _>>()?;
+
+        points.push(point);
+    }
+
+    Ok(points)
+}
+
+fn parse_binary(path: &str, bytes: &[u8], dimension: usize) -> Result<Vec<Vec<f64>>, PointsError> {
+    if dimension == 0 || !bytes.len().is_multiple_of(8 * dimension) {
+        return Err(PointsError::Syntax {
+            path: path.to_string(),
+            position: format!("byte {}", bytes.len()),
+            message: format!("the size of the file is not a multiple of {} (8 * dimension)", 8 * dimension),
+        });
+    }
+
+    Ok(bytes.chunks(8 * dimension).map(
+        |point| point.chunks(8).map(|n| f64::from_le_bytes(n.try_into().unwrap())).collect()
+    ).collect())
+}
+
+enum Json {
+    Number(f64),
+    Array(Vec<Json>),
+}
+
+fn parse_json(path: &str, s: &str) -> Result<Vec<Vec<f64>>, PointsError> {
+    let syntax_error = |position: usize, message: String| PointsError::Syntax {
+        path: path.to_string(),
+        position: format!("byte {position}"),
+        message,
+    };
+
+    let bytes = s.as_bytes();
+    let mut cursor = 0;
+    let value = parse_json_value(bytes, &mut cursor).map_err(|(position, message)| syntax_error(position, message))?;
+    skip_whitespace(bytes, &mut cursor);
+
+    if cursor < bytes.len() {
+        return Err(syntax_error(cursor, String::from("unexpected characters after the value")));
+    }
+
+    let not_vectors = || syntax_error(0, String::from("expected an array of numbers or an array of arrays of numbers"));
+
+    match value {
+        Json::Array(elements) if elements.iter().all(|e| matches!(e, Json::Number(_))) => Ok(vec![
+            elements.into_iter().map(|e| match e { Json::Number(n) => n, Json::Array(_) => unreachable!() }).collect()
+        ]),
+        Json::Array(elements) => elements.into_iter().map(
+            |e| match e {
+                Json::Array(point) => point.into_iter().map(
+                    |n| match n { Json::Number(n) => Ok(n), Json::Array(_) => Err(not_vectors()) }
+                ).collect(),
+                Json::Number(_) => Err(not_vectors()),
+            }
+        ).collect(),
+        Json::Number(_) => Err(not_vectors()),
+    }
+}
+
+// Err((byte offset, message))
+fn parse_json_value(bytes: &[u8], cursor: &mut usize) -> Result<Json, (usize, String)> {
+    skip_whitespace(bytes, cursor);
+
+    match bytes.get(*cursor) {
+        Some(b'[') => {
+            *cursor += 1;
+            let mut elements = vec![];
+            skip_whitespace(bytes, cursor);
+
+            if bytes.get(*cursor) == Some(&b']') {
+                *cursor += 1;
+                return Ok(Json::Array(elements));
+            }
+
+            loop {
+                elements.push(parse_json_value(bytes, cursor)?);
+                skip_whitespace(bytes, cursor);
+
+                match bytes.get(*cursor) {
+                    Some(b',') => { *cursor += 1; },
+                    Some(b']') => {
+                        *cursor += 1;
+                        return Ok(Json::Array(elements));
+                    },
+                    _ => { return Err((*cursor, String::from("expected `,` or `]`"))); },
+                }
+            }
+        },
+        Some(c) if c.is_ascii_digit() || *c == b'-' => {
+            let start = *cursor;
+
+            while let Some(c) = bytes.get(*cursor) {
+                if c.is_ascii_digit() || b"+-.eE".contains(c) {
+                    *cursor += 1;
+                }
+
+                else {
+                    break;
+                }
+            }
+
+            // the slice is ascii
+            let n = std::str::from_utf8(&bytes[start..*cursor]).unwrap();
+
+            n.parse::<f64>().map(Json::Number).map_err(|_| (start, format!("`{n}` is not a number")))
+        },
+        Some(_) => Err((*cursor, String::from("expected a number or an array"))),
+        None => Err((*cursor, String::from("unexpected end of file"))),
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], cursor: &mut usize) {
+    while bytes.get(*cursor).map(|c| c.is_ascii_whitespace()).unwrap_or(false) {
+        *cursor += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::{remove_file, temp_path, write_bytes, write_string, WriteMode};
+
+    #[test]
+    fn formats() {
+        let expected = vec![vec![0.5, -1.0, 2.5e-3], vec![0.0, 1.0, 1e10]];
+
+        let csv = temp_path("formats.csv");
+        write_string(&csv, "# comment\n0.5, -1.0, 2.5e-3\n\n0, 1, 1e10\n", WriteMode::CreateOrTruncate).unwrap();
+        assert_eq!(load_points(&csv, 3).map_err(|e| e.render_error()).unwrap(), expected);
+        remove_file(&csv).unwrap();
+
+        let json = temp_path("formats.json");
+        write_string(&json, "[[0.5, -1.0, 2.5e-3],\n [0, 1, 1e10]]", WriteMode::CreateOrTruncate).unwrap();
+        assert_eq!(load_points(&json, 3).map_err(|e| e.render_error()).unwrap(), expected);
+        write_string(&json, " [0.5, -1.0, 2.5e-3] ", WriteMode::CreateOrTruncate).unwrap();
+        assert_eq!(load_points(&json, 3).map_err(|e| e.render_error()).unwrap(), expected[..1]);
+        remove_file(&json).unwrap();
+
+        let binary = temp_path("formats.bin");
+        let bytes = expected.iter().flatten().flat_map(|n| n.to_le_bytes()).collect::<Vec<_>>();
+        write_bytes(&binary, &bytes, WriteMode::CreateOrTruncate).unwrap();
+        assert_eq!(load_points(&binary, 3).map_err(|e| e.render_error()).unwrap(), expected);
+        assert!(matches!(load_points(&binary, 4), Err(PointsError::Syntax { .. })));
+        remove_file(&binary).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let csv = temp_path("errors.csv");
+
+        write_string(&csv, "1, 2\n3, 4, 5\n", WriteMode::CreateOrTruncate).unwrap();
+        assert!(matches!(load_points(&csv, 2), Err(PointsError::DimensionMismatch { index: 1, expected: 2, got: 3, .. })));
+
+        write_string(&csv, "1, 2\n3, x\n", WriteMode::CreateOrTruncate).unwrap();
+
+        match load_points(&csv, 2) {
+            Err(PointsError::Syntax { position, .. }) => { assert_eq!(position, "line 2"); },
+            _ => panic!("`x` has to be a syntax error"),
+        }
+
+        write_string(&csv, "# nothing\n", WriteMode::CreateOrTruncate).unwrap();
+        assert!(matches!(load_points(&csv, 2), Err(PointsError::Empty { .. })));
+        remove_file(&csv).unwrap();
+
+        let json = temp_path("errors.json");
+
+        for content in ["[[1, 2], [3, 4]", "[[1, 2], 3]", "[1, 2] 3", "{\"a\": 1}", "[1, [2]]"] {
+            write_string(&json, content, WriteMode::CreateOrTruncate).unwrap();
+            assert!(matches!(load_points(&json, 2), Err(PointsError::Syntax { .. })), "`{content}` has to be a syntax error");
+        }
+
+        remove_file(&json).unwrap();
+    }
+}
@@ -1,2 +1,4 @@
-// pub mod graph;
-// pub mod lstm;
+pub mod benchmarks;
+pub mod graph;
+pub mod lstm;
+pub mod text;
@@ -0,0 +1,156 @@
+use std::f64::consts::{E, PI};
+
+// Standard test functions for optimizers. They work in any dimension, and their optima are known,
+// so they're used to check whether a change of the optimizer makes it better or worse.
+// See https://www.sfu.ca/~ssurjano/optimization.html for the definitions.
+//
+// The global minimum of every function is 0. They're computed in `f64`, so the optimizer runs in `f64`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Benchmark {
+    Sphere,
+    Rosenbrock,
+    Rastrigin,
+    Ackley,
+    Griewank,
+    Schwefel,
+    Levy,
+}
+
+impl Benchmark {
+    pub const ALL: [Benchmark; 7] = [
+        Benchmark::Sphere,
+        Benchmark::Rosenbrock,
+        Benchmark::Rastrigin,
+        Benchmark::Ackley,
+        Benchmark::Griewank,
+        Benchmark::Schwefel,
+        Benchmark::Levy,
+    ];
+
+    // case-insensitive, e.g. `nonlinear_opt run benchmark rosenbrock`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Benchmark::ALL.iter().find(
+            |benchmark| benchmark.name().eq_ignore_ascii_case(name)
+        ).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Benchmark::Sphere => "sphere",
+            Benchmark::Rosenbrock => "rosenbrock",
+            Benchmark::Rastrigin => "rastrigin",
+            Benchmark::Ackley => "ackley",
+            Benchmark::Griewank => "griewank",
+            Benchmark::Schwefel => "schwefel",
+            Benchmark::Levy => "levy",
+        }
+    }
+
+    pub fn f(&self, x: &[f64]) -> f64 {
+        let d = x.len() as f64;
+
+        match self {
+            // sum(x_i^2)
+            Benchmark::Sphere => x.iter().map(|x| x * x).sum::<f64>(),
+
+            // sum(100 * (x_(i+1) - x_i^2)^2 + (x_i - 1)^2)
+            Benchmark::Rosenbrock => x.windows(2).map(
+                |w| 100.0 * (w[1] - w[0] * w[0]).powi(2) + (w[0] - 1.0).powi(2)
+            ).sum::<f64>(),
+
+            // 10d + sum(x_i^2 - 10cos(2πx_i))
+            Benchmark::Rastrigin => 10.0 * d + x.iter().map(
+                |x| x * x - 10.0 * (2.0 * PI * x).cos()
+            ).sum::<f64>(),
+
+            // -20exp(-0.2sqrt(sum(x_i^2) / d)) - exp(sum(cos(2πx_i)) / d) + 20 + e
+            Benchmark::Ackley => {
+                let sum_sq = x.iter().map(|x| x * x).sum::<f64>();
+                let sum_cos = x.iter().map(|x| (2.0 * PI * x).cos()).sum::<f64>();
+
+                -20.0 * (-0.2 * (sum_sq / d).sqrt()).exp() - (sum_cos / d).exp() + 20.0 + E
+            },
+
+            // sum(x_i^2) / 4000 - prod(cos(x_i / sqrt(i + 1))) + 1
+            Benchmark::Griewank => {
+                let sum_sq = x.iter().map(|x| x * x).sum::<f64>();
+                let prod_cos = x.iter().enumerate().map(
+                    |(i, x)| (x / ((i + 1) as f64).sqrt()).cos()
+                ).product::<f64>();
+
+                sum_sq / 4000.0 - prod_cos + 1.0
+            },
+
+            // 418.9829d - sum(x_i sin(sqrt(|x_i|)))
+            Benchmark::Schwefel => 418.9828872724338 * d - x.iter().map(
+                |x| x * x.abs().sqrt().sin()
+            ).sum::<f64>(),
+
+            // w_i = 1 + (x_i - 1) / 4
+            // sin^2(πw_1) + sum((w_i - 1)^2 (1 + 10sin^2(πw_i + 1))) + (w_d - 1)^2 (1 + sin^2(2πw_d))
+            Benchmark::Levy => {
+                let w = x.iter().map(|x| 1.0 + (x - 1.0) / 4.0).collect::<Vec<_>>();
+                let last = w[w.len() - 1];
+
+                (PI * w[0]).sin().powi(2)
+                + w[..(w.len() - 1)].iter().map(
+                    |w| (w - 1.0).powi(2) * (1.0 + 10.0 * (PI * w + 1.0).sin().powi(2))
+                ).sum::<f64>()
+                + (last - 1.0).powi(2) * (1.0 + (2.0 * PI * last).sin().powi(2))
+            },
+        }
+    }
+
+    // the global minimum: `f(optimum(d))` is (almost) 0
+    // `nonlinear_opt bench` reports how far the best parameters are from it
+    pub fn optimum(&self, dimension: usize) -> Vec<f64> {
+        let x = match self {
+            Benchmark::Sphere
+            | Benchmark::Rastrigin
+            | Benchmark::Ackley
+            | Benchmark::Griewank => 0.0,
+            Benchmark::Rosenbrock | Benchmark::Levy => 1.0,
+            Benchmark::Schwefel => 420.9687,
+        };
+
+        vec![x; dimension]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optima_are_zero() {
+        for benchmark in Benchmark::ALL {
+            for dimension in [1, 2, 5, 32] {
+                let optimum = benchmark.optimum(dimension);
+                let loss = benchmark.f(&optimum);
+
+                // the optimum of Schwefel is rounded to 4 decimal places
+                assert!(loss.abs() < 1e-3, "{}({dimension}): {loss}", benchmark.name());
+
+                // it's the minimum, at least locally (Rosenbrock is constant in 1 dimension)
+                if dimension == 1 {
+                    continue;
+                }
+
+                let mut moved = optimum.clone();
+                moved[0] += 0.01;
+                assert!(benchmark.f(&moved) > loss, "{}({dimension})", benchmark.name());
+            }
+        }
+    }
+
+    #[test]
+    fn names() {
+        for benchmark in Benchmark::ALL {
+            assert_eq!(Benchmark::from_name(benchmark.name()), Some(benchmark));
+            assert_eq!(Benchmark::from_name(&benchmark.name().to_uppercase()), Some(benchmark));
+        }
+
+        assert_eq!(Benchmark::from_name("sphere2"), None);
+        assert_eq!(Benchmark::from_name(""), None);
+    }
+}
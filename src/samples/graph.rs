@@ -1,98 +1,664 @@
-use crate::config::ParamType;
+
+// `Objective::Native` runs in `f32`
+type ParamType = f32;
 
 // This sample is a graph optimizer based on [force-directed graph drawing](https://en.wikipedia.org/wiki/Force-directed_graph_drawing).
 // This sample is to test the optimizer, not the graph optimizer. If you want a graph drawer, just use graphviz.
 
-// PARAM_SIZE has to be VERTEX_COUNT * 2
-
-// configure 3 constants below to optimize your graph
-// example from https://baehyunsol.github.io/IRs-of-Rust.html
-const EDGE_COUNT: usize = 10;
-pub const VERTEX_COUNT: usize = 9;
-const EDGES: [(usize, usize); EDGE_COUNT] = [
-    (0, 1),
-    (1, 2),
-    (1, 3),
-    (2, 4),
-    (3, 5),
-    (4, 5),
-    (5, 6),
-    (5, 7),
-    (6, 7),
-    (6, 8),
-];
-
-const EDGE_LENGTH: ParamType = 1.0;
-
-// it does 2 things:
-//    1, prevent the graph from rotating 
-//    2, can represent real-world data, if exists
-const FIXED_VERTICES: [
-    (usize, ParamType, ParamType);  // (index, x, y)
-    1   // the number of fixed vertices
-] = [
-    // let's just fix this vertex at origin
-    (0, 0.0, 0.0),
-];
+// The graph is read from `GRAPH_PATH` at the first call to `f`, so you can change the graph without recompiling.
+// Use `Objective::NativeWithDimension { f, dimension }`: the parameters are (x, y) of each vertex.
+//
+// If the extension is `.dot` or `.gv`, it's a subset of the DOT language of graphviz:
+// - `graph` or `digraph` with a single body. The direction of an edge doesn't matter. Subgraphs are not supported.
+// - edges (`a -- b -- c`, `a -> b`) and vertices (`a`), with optional attributes
+// - `len`: the length of an edge, which has to be positive (default: `DEFAULT_EDGE_LENGTH`). `edge [len=2]` changes the default.
+// - `pos="x,y!"` (or `pos="x,y"` with `pin=true`): fixes a vertex at (x, y)
+// - the other attributes and `graph`/`node` attributes are ignored
+//
+// ```nohighlight
+// graph {
+//     a -- b -- c;
+//     b -- d [len=2];
+//     a [pos="0,0!"];
+// }
+// ```
+//
+// Otherwise, it's an edge list: a line per edge, with an optional positive length. A line with a single name adds a vertex,
+// and `fix <name> <x> <y>` fixes a vertex, so a vertex cannot be named `fix`. Empty lines and lines that start with `#` are ignored.
+//
+// ```nohighlight
+// a b
+// b c
+// b d 2.0
+// fix a 0 0
+// ```
+//
+// Fixed vertices prevent the graph from rotating, and can represent real-world data, if exists.
+// If the file fixes no vertex, the first vertex is fixed at the origin.
+
+use crate::files::{extension, read_string, FileError};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// configure the constant below to optimize your graph
+const GRAPH_PATH: &str = "./graph.dot";
+
+const DEFAULT_EDGE_LENGTH: ParamType = 1.0;
+
+pub struct Graph {
+    // in the order of their first appearance
+    pub vertices: Vec<String>,
+    pub edges: Vec<Edge>,
+    pub fixed_vertices: Vec<FixedVertex>,
+}
 
-pub fn f(parameters: &[ParamType]) -> ParamType {
-    assert_eq!(parameters.len(), VERTEX_COUNT * 2);
-    let mut loss: ParamType = 0.0;
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub length: ParamType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedVertex {
+    pub index: usize,
+    pub x: ParamType,
+    pub y: ParamType,
+}
+
+pub enum GraphError {
+    File(FileError),
+    Syntax {
+        path: String,
+
+        // 1-based
+        line: usize,
+        message: String,
+    },
+    Empty {
+        path: String,
+    },
+}
+
+impl GraphError {
+    pub fn render_error(&self) -> String {
+        match self {
+            GraphError::File(e) => e.render_error(),
+            GraphError::Syntax { path, line, message } => format!("`{path}`, line {line}: {message}"),
+            GraphError::Empty { path } => format!("`{path}` doesn't have any vertex"),
+        }
+    }
+}
+
+impl Graph {
+    pub fn load(path: &str) -> Result<Self, GraphError> {
+        let s = read_string(path).map_err(GraphError::File)?;
+
+        match extension(path).map_err(GraphError::File)?.as_deref() {
+            Some("dot") | Some("gv") => Graph::parse_dot(path, &s),
+            _ => Graph::parse_edge_list(path, &s),
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
 
-    for i in 0..VERTEX_COUNT {
-        let x1 = parameters[i * 2];
-        let y1 = parameters[i * 2 + 1];
+    // `path` is only for error messages
+    pub fn parse_edge_list(path: &str, s: &str) -> Result<Self, GraphError> {
+        let mut builder = Builder::new(path);
 
-        for j in (i + 1)..VERTEX_COUNT {
-            let x2 = parameters[j * 2];
-            let y2 = parameters[j * 2 + 1];
+        for (index, line) in s.lines().enumerate() {
+            let line_no = index + 1;
+            let line = line.trim();
 
-            let dist = (x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
 
-            if dist == 0.0 {
-                loss += 1e15;  //  a big enough number
+            let words = line.split_whitespace().collect::<Vec<_>>();
+
+            match words.as_slice() {
+                ["fix", name, x, y] => {
+                    let x = parse_number(path, line_no, x)?;
+                    let y = parse_number(path, line_no, y)?;
+                    builder.fix(name, x, y);
+                },
+                ["fix", ..] => {
+                    return Err(syntax_error(path, line_no, format!("`{line}` is not `fix <name> <x> <y>`")));
+                },
+                [name] => {
+                    builder.vertex(name);
+                },
+                [from, to] => {
+                    builder.edge(from, to, DEFAULT_EDGE_LENGTH);
+                },
+                [from, to, length] => {
+                    let length = parse_length(path, line_no, length)?;
+                    builder.edge(from, to, length);
+                },
+                _ => {
+                    return Err(syntax_error(path, line_no, format!("`{line}` is not an edge")));
+                },
             }
+        }
+
+        builder.build()
+    }
 
-            else {
-                loss += 1.0 / dist;
+    // `path` is only for error messages
+    pub fn parse_dot(path: &str, s: &str) -> Result<Self, GraphError> {
+        let tokens = tokenize_dot(path, s)?;
+        let mut parser = DotParser { path, tokens: &tokens, cursor: 0 };
+        let mut builder = Builder::new(path);
+        let mut default_edge_length = DEFAULT_EDGE_LENGTH;
+
+        if parser.peek_id() == Some("strict") {
+            parser.cursor += 1;
+        }
+
+        match parser.peek_id() {
+            Some("graph") | Some("digraph") => { parser.cursor += 1; },
+            _ => { return Err(parser.error("a DOT file starts with `graph` or `digraph`")); },
+        }
+
+        // the name of the graph
+        if parser.peek_id().is_some() {
+            parser.cursor += 1;
+        }
+
+        parser.expect(DotToken::Open('{'))?;
+
+        loop {
+            match parser.peek() {
+                Some(DotToken::Close('}')) => {
+                    parser.cursor += 1;
+                    break;
+                },
+                Some(DotToken::Semicolon) => {
+                    parser.cursor += 1;
+                },
+                Some(DotToken::Id(id)) if id == "subgraph" => {
+                    return Err(parser.error("subgraphs are not supported"));
+                },
+                Some(DotToken::Open('{')) => {
+                    return Err(parser.error("subgraphs are not supported"));
+                },
+                Some(DotToken::Id(id)) if ["graph", "node", "edge"].contains(&id.as_str()) && parser.peek_at(1) == Some(&DotToken::Open('[')) => {
+                    let kind = id.clone();
+                    parser.cursor += 1;
+                    let attributes = parser.attributes()?;
+
+                    if kind == "edge" {
+                        if let Some((line, len)) = attributes.get("len") {
+                            default_edge_length = parse_length(path, *line, len)?;
+                        }
+                    }
+                },
+                Some(DotToken::Id(_)) if parser.peek_at(1) == Some(&DotToken::Equal) => {
+                    // `rankdir = LR`
+                    parser.id()?;
+                    parser.expect(DotToken::Equal)?;
+                    parser.id()?;
+                },
+                Some(DotToken::Id(_)) => {
+                    let mut names = vec![parser.id()?];
+
+                    while let Some(DotToken::Edge) = parser.peek() {
+                        parser.cursor += 1;
+                        names.push(parser.id()?);
+                    }
+
+                    let attributes = if parser.peek() == Some(&DotToken::Open('[')) { parser.attributes()? } else { HashMap::new() };
+
+                    if names.len() == 1 {
+                        builder.vertex(&names[0]);
+                        let pinned = attributes.get("pin").map(|(_, pin)| pin == "true").unwrap_or(false);
+
+                        if let Some((line, pos)) = attributes.get("pos") {
+                            let fixed_pos = pos.strip_suffix('!').or(if pinned { Some(pos.as_str()) } else { None });
+
+                            if let Some(pos) = fixed_pos {
+                                let (x, y) = parse_pos(path, *line, pos)?;
+                                builder.fix(&names[0], x, y);
+                            }
+                        }
+                    }
+
+                    else {
+                        let length = match attributes.get("len") {
+                            Some((line, len)) => parse_length(path, *line, len)?,
+                            None => default_edge_length,
+                        };
+
+                        for pair in names.windows(2) {
+                            builder.edge(&pair[0], &pair[1], length);
+                        }
+                    }
+                },
+                Some(_) => {
+                    return Err(parser.error("expected a statement"));
+                },
+                None => {
+                    return Err(parser.error("the graph is not closed with `}`"));
+                },
             }
         }
+
+        if parser.cursor < tokens.len() {
+            return Err(parser.error("only a single graph is supported"));
+        }
+
+        builder.build()
+    }
+
+    pub fn loss(&self, parameters: &[ParamType]) -> ParamType {
+        assert_eq!(parameters.len(), self.vertex_count() * 2, "`Config.dimension` has to be `dimension()` or None");
+        let mut loss: ParamType = 0.0;
+
+        for i in 0..self.vertex_count() {
+            let x1 = parameters[i * 2];
+            let y1 = parameters[i * 2 + 1];
+
+            for j in (i + 1)..self.vertex_count() {
+                let x2 = parameters[j * 2];
+                let y2 = parameters[j * 2 + 1];
+
+                let dist = (x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2);
+
+                if dist == 0.0 {
+                    loss += 1e15;  //  a big enough number
+                }
+
+                else {
+                    loss += 1.0 / dist;
+                }
+            }
+        }
+
+        for Edge { from, to, length } in self.edges.iter() {
+            let x1 = parameters[from * 2];
+            let y1 = parameters[from * 2 + 1];
+
+            let x2 = parameters[to * 2];
+            let y2 = parameters[to * 2 + 1];
+
+            let dist = ((x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2)).sqrt();
+            let force = (dist - length).abs();
+            loss += force;
+        }
+
+        for FixedVertex { index, x: ref_x, y: ref_y } in self.fixed_vertices.iter() {
+            let curr_x = parameters[index * 2];
+            let curr_y = parameters[index * 2 + 1];
+
+            let dist = (curr_x - ref_x) * (curr_x - ref_x) + (curr_y - ref_y) * (curr_y - ref_y);
+
+            loss += dist;  // it has to be stronger than other forces ... really?
+        }
+
+        loss
+    }
+}
+
+struct Builder<'a> {
+    path: &'a str,
+    vertices: Vec<String>,
+    indices: HashMap<String, usize>,
+    edges: Vec<Edge>,
+    fixed_vertices: Vec<FixedVertex>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(path: &'a str) -> Self {
+        Builder {
+            path,
+            vertices: vec![],
+            indices: HashMap::new(),
+            edges: vec![],
+            fixed_vertices: vec![],
+        }
+    }
+
+    fn vertex(&mut self, name: &str) -> usize {
+        match self.indices.get(name) {
+            Some(index) => *index,
+            None => {
+                self.vertices.push(name.to_string());
+                self.indices.insert(name.to_string(), self.vertices.len() - 1);
+
+                self.vertices.len() - 1
+            },
+        }
+    }
+
+    fn edge(&mut self, from: &str, to: &str, length: ParamType) {
+        let from = self.vertex(from);
+        let to = self.vertex(to);
+        self.edges.push(Edge { from, to, length });
+    }
+
+    // the last position wins
+    fn fix(&mut self, name: &str, x: ParamType, y: ParamType) {
+        let index = self.vertex(name);
+        self.fixed_vertices.retain(|fixed| fixed.index != index);
+        self.fixed_vertices.push(FixedVertex { index, x, y });
+    }
+
+    fn build(mut self) -> Result<Graph, GraphError> {
+        if self.vertices.is_empty() {
+            return Err(GraphError::Empty { path: self.path.to_string() });
+        }
+
+        if self.fixed_vertices.is_empty() {
+            self.fixed_vertices.push(FixedVertex { index: 0, x: 0.0, y: 0.0 });
+        }
+
+        Ok(Graph {
+            vertices: self.vertices,
+            edges: self.edges,
+            fixed_vertices: self.fixed_vertices,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum DotToken {
+    // names, numbers and quoted strings (without the quotes)
+    Id(String),
+    Open(char),
+    Close(char),
+    Equal,
+    Comma,
+    Semicolon,
+
+    // `--` or `->`
+    Edge,
+}
+
+// (token, 1-based line)
+fn tokenize_dot(path: &str, s: &str) -> Result<Vec<(DotToken, usize)>, GraphError> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut cursor = 0;
+
+    while let Some(c) = chars.get(cursor) {
+        match c {
+            '\n' => {
+                line += 1;
+                cursor += 1;
+            },
+            c if c.is_whitespace() => {
+                cursor += 1;
+            },
+            '#' => {
+                while cursor < chars.len() && chars[cursor] != '\n' {
+                    cursor += 1;
+                }
+            },
+            '/' if chars.get(cursor + 1) == Some(&'/') => {
+                while cursor < chars.len() && chars[cursor] != '\n' {
+                    cursor += 1;
+                }
+            },
+            '/' if chars.get(cursor + 1) == Some(&'*') => {
+                let start_line = line;
+                cursor += 2;
+
+                loop {
+                    match chars.get(cursor) {
+                        Some('*') if chars.get(cursor + 1) == Some(&'/') => {
+                            cursor += 2;
+                            break;
+                        },
+                        Some(c) => {
+                            if *c == '\n' {
+                                line += 1;
+                            }
+
+                            cursor += 1;
+                        },
+                        None => {
+                            return Err(syntax_error(path, start_line, String::from("the comment is not closed")));
+                        },
+                    }
+                }
+            },
+            '{' | '[' => {
+                tokens.push((DotToken::Open(*c), line));
+                cursor += 1;
+            },
+            '}' | ']' => {
+                tokens.push((DotToken::Close(*c), line));
+                cursor += 1;
+            },
+            '=' => {
+                tokens.push((DotToken::Equal, line));
+                cursor += 1;
+            },
+            ',' => {
+                tokens.push((DotToken::Comma, line));
+                cursor += 1;
+            },
+            ';' => {
+                tokens.push((DotToken::Semicolon, line));
+                cursor += 1;
+            },
+            '-' if matches!(chars.get(cursor + 1), Some('-') | Some('>')) => {
+                tokens.push((DotToken::Edge, line));
+                cursor += 2;
+            },
+            '"' => {
+                let start_line = line;
+                let mut id = String::new();
+                cursor += 1;
+
+                loop {
+                    match chars.get(cursor) {
+                        Some('"') => {
+                            cursor += 1;
+                            break;
+                        },
+                        Some('\\') if chars.get(cursor + 1) == Some(&'"') => {
+                            id.push('"');
+                            cursor += 2;
+                        },
+                        Some(c) => {
+                            if *c == '\n' {
+                                line += 1;
+                            }
+
+                            id.push(*c);
+                            cursor += 1;
+                        },
+                        None => {
+                            return Err(syntax_error(path, start_line, String::from("the string is not closed")));
+                        },
+                    }
+                }
+
+                tokens.push((DotToken::Id(id), start_line));
+            },
+            c if c.is_alphanumeric() || *c == '_' || *c == '.' || *c == '-' => {
+                let mut id = String::new();
+
+                while let Some(c) = chars.get(cursor) {
+                    // `a--b` is an edge, not a name
+                    if *c == '-' && !id.is_empty() && matches!(chars.get(cursor + 1), Some('-') | Some('>')) {
+                        break;
+                    }
+
+                    if c.is_alphanumeric() || *c == '_' || *c == '.' || *c == '-' {
+                        id.push(*c);
+                        cursor += 1;
+                    }
+
+                    else {
+                        break;
+                    }
+                }
+
+                tokens.push((DotToken::Id(id), line));
+            },
+            c => {
+                return Err(syntax_error(path, line, format!("unexpected character `{c}`")));
+            },
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct DotParser<'a> {
+    path: &'a str,
+    tokens: &'a [(DotToken, usize)],
+    cursor: usize,
+}
+
+impl DotParser<'_> {
+    fn peek(&self) -> Option<&DotToken> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&DotToken> {
+        self.tokens.get(self.cursor + offset).map(|(token, _)| token)
     }
 
-    for (v1, v2) in EDGES.iter() {
-        let x1 = parameters[v1 * 2];
-        let y1 = parameters[v1 * 2 + 1];
+    fn peek_id(&self) -> Option<&str> {
+        match self.peek() {
+            Some(DotToken::Id(id)) => Some(id),
+            _ => None,
+        }
+    }
 
-        let x2 = parameters[v2 * 2];
-        let y2 = parameters[v2 * 2 + 1];
+    fn line(&self) -> usize {
+        self.tokens.get(self.cursor).or(self.tokens.last()).map(|(_, line)| *line).unwrap_or(1)
+    }
 
-        let dist = ((x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2)).sqrt();
-        let force = (dist - EDGE_LENGTH).abs();
-        loss += force;
+    fn error(&self, message: &str) -> GraphError {
+        syntax_error(self.path, self.line(), message.to_string())
     }
 
-    for (index, ref_x, ref_y) in FIXED_VERTICES.iter() {
-        let curr_x = parameters[index * 2];
-        let curr_y = parameters[index * 2 + 1];
+    fn expect(&mut self, token: DotToken) -> Result<(), GraphError> {
+        if self.peek() == Some(&token) {
+            self.cursor += 1;
+            Ok(())
+        }
 
-        let dist = (curr_x - ref_x) * (curr_x - ref_x) + (curr_y - ref_y) * (curr_y - ref_y);
+        else {
+            Err(self.error(&format!("expected {token:?}")))
+        }
+    }
 
-        loss += dist;  // it has to be stronger than other forces ... really?
+    fn id(&mut self) -> Result<String, GraphError> {
+        match self.peek() {
+            Some(DotToken::Id(id)) => {
+                let id = id.clone();
+                self.cursor += 1;
+                Ok(id)
+            },
+            _ => Err(self.error("expected a name")),
+        }
     }
 
-    loss
+    // `[key=value, key=value; ...]`, and the line of each value
+    fn attributes(&mut self) -> Result<HashMap<String, (usize, String)>, GraphError> {
+        let mut result = HashMap::new();
+        self.expect(DotToken::Open('['))?;
+
+        loop {
+            match self.peek() {
+                Some(DotToken::Close(']')) => {
+                    self.cursor += 1;
+                    break;
+                },
+                Some(DotToken::Comma) | Some(DotToken::Semicolon) => {
+                    self.cursor += 1;
+                },
+                _ => {
+                    let key = self.id()?;
+                    self.expect(DotToken::Equal)?;
+                    let line = self.line();
+                    let value = self.id()?;
+                    result.insert(key, (line, value));
+                },
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+fn syntax_error(path: &str, line: usize, message: String) -> GraphError {
+    GraphError::Syntax { path: path.to_string(), line, message }
 }
 
+fn parse_number(path: &str, line: usize, s: &str) -> Result<ParamType, GraphError> {
+    match s.trim().parse::<ParamType>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(syntax_error(path, line, format!("`{s}` is not a number"))),
+    }
+}
+
+// the length of an edge has to be positive
+fn parse_length(path: &str, line: usize, s: &str) -> Result<ParamType, GraphError> {
+    match parse_number(path, line, s)? {
+        n if n > 0.0 => Ok(n),
+        _ => Err(syntax_error(path, line, format!("`{s}` is not a positive length"))),
+    }
+}
+
+// "x,y"
+fn parse_pos(path: &str, line: usize, s: &str) -> Result<(ParamType, ParamType), GraphError> {
+    match s.split_once(',') {
+        Some((x, y)) => Ok((parse_number(path, line, x)?, parse_number(path, line, y)?)),
+        None => Err(syntax_error(path, line, format!("`{s}` is not a position"))),
+    }
+}
+
+static GRAPH: OnceLock<Graph> = OnceLock::new();
+
+// the graph at `GRAPH_PATH`. It panics if the file cannot be loaded.
+pub fn graph() -> &'static Graph {
+    GRAPH.get_or_init(
+        || match Graph::load(GRAPH_PATH) {
+            Ok(graph) => graph,
+            Err(e) => panic!("cannot load the graph: {}", e.render_error()),
+        }
+    )
+}
+
+// the number of parameters of `f`, as `Objective::NativeWithDimension`
+// it loads the graph, so it panics if the file cannot be loaded
+pub fn dimension() -> usize {
+    graph().vertex_count() * 2
+}
+
+pub fn f(parameters: &[ParamType]) -> ParamType {
+    graph().loss(parameters)
+}
+
+use crate::float::Float;
+use crate::groups::ParameterGroup;
 use crate::state::State;
+use crate::stats::Stats;
 use crate::files::write_string;
 
-pub fn visualizer(states: &[State]) {
+// the default visualizer, plus a `./draw<index>.py` per state that draws the layout with pygame
+// `config::visualizer` calls it instead of printing when you lay out a graph, so nothing else uses it
+#[allow(dead_code)]
+pub fn visualizer<T: Float>(states: &[State<T>], stats: &Stats, groups: &[ParameterGroup]) {
     clearscreen::clear().unwrap();
 
     for state in states.iter() {
-        println!("\n{}", state.pretty_print());
+        println!("\n{}", state.pretty_print(groups));
     }
 
+    println!("\n{}", stats.pretty_print());
+
+    let edges = graph().edges.iter().map(|edge| (edge.from, edge.to)).collect::<Vec<_>>();
+
     // I don't want to introduce another dependency for this sample
     for (index, state) in states.iter().enumerate() {
         let python = format!("
@@ -102,7 +668,7 @@ pygame.init()
 screen = pygame.display.set_mode((800, 800))
 
 parameters = {:?}
-edges = {EDGES:?}
+edges = {edges:?}
 
 ZOOM = 120
 OFFSET = 400
@@ -143,3 +709,91 @@ while True:
         ).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot() {
+        let graph = Graph::parse_dot("test.dot", r#"
+            // a comment
+            strict graph G {
+                rankdir = LR;
+                edge [len=2, color=red]
+                a -- b -- "c d" [len=0.5];
+                b -> e
+                /* a
+                   block comment */
+                a [pos="1,-2!"];
+                e [pos="3,4", pin=true]
+                f
+            }
+        "#).map_err(|e| e.render_error()).unwrap();
+
+        assert_eq!(graph.vertices, vec!["a", "b", "c d", "e", "f"]);
+        assert_eq!(graph.edges, vec![
+            Edge { from: 0, to: 1, length: 0.5 },
+            Edge { from: 1, to: 2, length: 0.5 },
+            Edge { from: 1, to: 3, length: 2.0 },
+        ]);
+        assert_eq!(graph.fixed_vertices, vec![
+            FixedVertex { index: 0, x: 1.0, y: -2.0 },
+            FixedVertex { index: 3, x: 3.0, y: 4.0 },
+        ]);
+
+        for (source, line) in [
+            ("graph { a -- b", 1),
+            ("graph {\n subgraph x { a } }", 2),
+            ("graph {\n\n a -- b [len=x] }", 3),
+            ("digraph { a -> \"b }", 1),
+            ("graph {\n a -- b [len=-1] }", 2),
+            ("graph {\n edge [len=0]\n a -- b }", 2),
+            ("graph {\n rankdir = ; a }", 2),
+            ("graph {\n rankdir = \n}", 3),
+        ] {
+            match Graph::parse_dot("test.dot", source) {
+                Err(GraphError::Syntax { line: l, .. }) => { assert_eq!(l, line, "{source}"); },
+                _ => panic!("`{source}` has to be a syntax error"),
+            }
+        }
+    }
+
+    #[test]
+    fn edge_list() {
+        let graph = Graph::parse_edge_list("test.txt", "# comment\na b\n\nb c 2.5\nd\n").map_err(|e| e.render_error()).unwrap();
+
+        assert_eq!(graph.vertex_count(), 4);
+        assert_eq!(graph.edges[1], Edge { from: 1, to: 2, length: 2.5 });
+
+        // the first vertex is fixed at the origin by default
+        assert_eq!(graph.fixed_vertices, vec![FixedVertex { index: 0, x: 0.0, y: 0.0 }]);
+
+        let graph = Graph::parse_edge_list("test.txt", "a b\nfix b 1 2\n").map_err(|e| e.render_error()).unwrap();
+        assert_eq!(graph.fixed_vertices, vec![FixedVertex { index: 1, x: 1.0, y: 2.0 }]);
+
+        assert!(matches!(Graph::parse_edge_list("test.txt", "a b\na b c d e\n"), Err(GraphError::Syntax { line: 2, .. })));
+        assert!(matches!(Graph::parse_edge_list("test.txt", "a b x\n"), Err(GraphError::Syntax { line: 1, .. })));
+
+        // `fix` takes exactly a name and a position, and lengths are positive
+        for source in ["fix a 1
+", "fix a
+", "fix a 1 2 3
+", "a b -1
+", "a b 0
+"] {
+            assert!(matches!(Graph::parse_edge_list("test.txt", source), Err(GraphError::Syntax { line: 1, .. })), "{source}");
+        }
+        assert!(matches!(Graph::parse_edge_list("test.txt", "# nothing\n"), Err(GraphError::Empty { .. })));
+    }
+
+    #[test]
+    fn loss() {
+        let graph = Graph::parse_edge_list("test.txt", "a b 2\n").map_err(|e| e.render_error()).unwrap();
+
+        // the edge has its length, and `a` is at the origin: only the repulsion is left
+        assert_eq!(graph.loss(&[0.0, 0.0, 2.0, 0.0]), 0.25);
+        assert!(graph.loss(&[0.0, 0.0, 1.0, 0.0]) > graph.loss(&[0.0, 0.0, 2.0, 0.0]));
+        assert!(graph.loss(&[1.0, 0.0, 3.0, 0.0]) > graph.loss(&[0.0, 0.0, 2.0, 0.0]));
+    }
+}
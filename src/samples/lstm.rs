@@ -1,15 +1,197 @@
-use crate::config::ParamType;
+use crate::groups::ParameterGroup;
+use crate::utils::mat_vec_parallel;
+use std::ops::Range;
 
-const INPUT_DIMENSION: usize = 256;
-const HIDDEN_STATE_DIMENSION: usize = 1024;
-const OUTPUT_DIMENSION: usize = 256;
+// `Objective::Native` runs in `f32`
+type ParamType = f32;
 
-const PARAM_SIZE: usize = HIDDEN_STATE_DIMENSION * 2 * OUTPUT_DIMENSION + OUTPUT_DIMENSION  // calc_output
-    + (HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION) * 4;  // f_t, i_t, o_t, g_t
+// the threads of each mat-vec of a time step
+// The optimizer already runs a worker per core, so it's 1 unless there are fewer workers than cores.
+const MAT_VEC_THREADS: usize = 1;
 
-// TODO: use `get_unchecked` (when you're sure that the code is robust enough)
+// in the order of the parameter vector
+pub const GATE_NAMES: [&str; 4] = ["f_t", "i_t", "o_t", "g_t"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Activation {
+    Sigmoid,
+    Tanh,
+}
+
+impl Activation {
+    pub fn apply(&self, x: ParamType) -> ParamType {
+        match self {
+            Activation::Sigmoid => sigmoid(x),
+            Activation::Tanh => x.tanh(),
+        }
+    }
+}
+
+// what `calc_output` applies to `W_y × (c_t <> h_t) + b_y`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputHead {
+    // each element is 0 ~ 1, independently
+    Sigmoid,
+
+    // the elements are a probability distribution
+    Softmax,
+}
+
+// the activations of the original LSTM, in the order of `GATE_NAMES`
+pub const DEFAULT_GATE_ACTIVATIONS: [Activation; 4] = [
+    Activation::Sigmoid,
+    Activation::Sigmoid,
+    Activation::Sigmoid,
+    Activation::Tanh,
+];
+
+// The dimensions and the activations of an LSTM. It has `param_size()` parameters (see `samples::text::dimension`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LstmSpec {
+    // x_t
+    pub input_dimension: usize,
+
+    // c_t and h_t
+    pub hidden_state_dimension: usize,
+
+    // Y
+    pub output_dimension: usize,
+
+    // of f_t, i_t, o_t and g_t (see `GATE_NAMES`)
+    pub gate_activations: [Activation; 4],
+    pub output_head: OutputHead,
+}
+
+impl Default for LstmSpec {
+    // the dimensions of the original byte-level LSTM, with the defaults of `LstmSpec::new`.
+    // `samples::text::SPEC` is the one with a softmax output.
+    fn default() -> Self {
+        LstmSpec::new(256, 1024, 256)
+    }
+}
+
+impl LstmSpec {
+    // `DEFAULT_GATE_ACTIVATIONS` and a sigmoid output
+    pub fn new(input_dimension: usize, hidden_state_dimension: usize, output_dimension: usize) -> Self {
+        LstmSpec {
+            input_dimension,
+            hidden_state_dimension,
+            output_dimension,
+            gate_activations: DEFAULT_GATE_ACTIVATIONS,
+            output_head: OutputHead::Sigmoid,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.input_dimension == 0 || self.hidden_state_dimension == 0 || self.output_dimension == 0 {
+            return Err(format!("every dimension of an LSTM has to be positive: {self:?}"));
+        }
+
+        Ok(())
+    }
+
+    // h_(t-1) <> x_t
+    pub fn concat_input_dimension(&self) -> usize {
+        self.hidden_state_dimension + self.input_dimension
+    }
+
+    pub fn param_size(&self) -> usize {
+        self.layout().output_bias.end
+    }
+
+    // the gates come first, each weight followed by its bias, and then the output layer
+    pub fn layout(&self) -> Layout {
+        let gate_weight_size = self.hidden_state_dimension * self.concat_input_dimension();
+        let gate_size = gate_weight_size + self.hidden_state_dimension;
+        let output_start = gate_size * 4;
+        let output_weight_size = self.hidden_state_dimension * 2 * self.output_dimension;
+
+        Layout {
+            gate_weights: std::array::from_fn(|index| (index * gate_size)..(index * gate_size + gate_weight_size)),
+            gate_biases: std::array::from_fn(|index| (index * gate_size + gate_weight_size)..((index + 1) * gate_size)),
+            output_weight: output_start..(output_start + output_weight_size),
+            output_bias: (output_start + output_weight_size)..(output_start + output_weight_size + self.output_dimension),
+        }
+    }
+
+    // `Config.parameter_groups` for this layout: the weights and the biases of the gates, and the output layer
+    pub fn parameter_groups(&self) -> Vec<ParameterGroup> {
+        let layout = self.layout();
+        let mut groups = vec![];
+
+        for (index, gate) in GATE_NAMES.iter().enumerate() {
+            groups.push(ParameterGroup::new(&format!("W_{gate}"), layout.gate_weights[index].clone()));
+            groups.push(ParameterGroup::new(&format!("b_{gate}"), layout.gate_biases[index].clone()));
+        }
+
+        groups.push(ParameterGroup::new("W_y", layout.output_weight));
+        groups.push(ParameterGroup::new("b_y", layout.output_bias));
+
+        groups
+    }
+
+    // `Scaling::Manual` for this layout: a weight is scaled by 1 / sqrt(the inputs of its row), so that
+    // a step changes every pre-activation about as much, and a bias by 1
+    pub fn scales(&self) -> Vec<f64> {
+        let layout = self.layout();
+        let mut scales = vec![1.0; self.param_size()];
+
+        for range in layout.gate_weights.iter() {
+            scales[range.clone()].fill(1.0 / (self.concat_input_dimension() as f64).sqrt());
+        }
+
+        scales[layout.output_weight].fill(1.0 / ((self.hidden_state_dimension * 2) as f64).sqrt());
+
+        scales
+    }
+}
+
+// Where each weight and bias lives in the parameter vector. Training and inference both slice
+// the parameters with it, so a vector saved by one is read correctly by the other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    // W_f, W_i, W_o and W_g (see `GATE_NAMES`), row-major: `hidden_state_dimension` rows of `concat_input_dimension()`
+    pub gate_weights: [Range<usize>; 4],
+
+    // b_f, b_i, b_o and b_g
+    pub gate_biases: [Range<usize>; 4],
+
+    // W_y, row-major: `output_dimension` rows of `hidden_state_dimension * 2`
+    pub output_weight: Range<usize>,
+
+    // b_y
+    pub output_bias: Range<usize>,
+}
+
+// The buffers of a time step. A caller that runs many time steps keeps one, so that a time step doesn't allocate.
+pub struct Scratch {
+    spec: LstmSpec,
+    layout: Layout,
+    concat_input: Vec<ParamType>,  // h_(t-1) <> x_t
+    forget_gate: Vec<ParamType>,   // f_t
+    input_gate: Vec<ParamType>,    // i_t
+    output_gate: Vec<ParamType>,   // o_t
+    activation: Vec<ParamType>,    // g_t
+    concat_state: Vec<ParamType>,  // c_t <> h_t
+}
+
+impl Scratch {
+    pub fn new(spec: &LstmSpec) -> Self {
+        Scratch {
+            spec: *spec,
+            layout: spec.layout(),
+            concat_input: vec![0.0; spec.concat_input_dimension()],
+            forget_gate: vec![0.0; spec.hidden_state_dimension],
+            input_gate: vec![0.0; spec.hidden_state_dimension],
+            output_gate: vec![0.0; spec.hidden_state_dimension],
+            activation: vec![0.0; spec.hidden_state_dimension],
+            concat_state: vec![0.0; spec.hidden_state_dimension * 2],
+        }
+    }
+}
 
 pub fn time_step(
+    spec: &LstmSpec,
     parameters: &[ParamType],
     cell_state: &[ParamType],    // c_(t-1)
     hidden_state: &[ParamType],  // h_(t-1)
@@ -20,85 +202,95 @@ pub fn time_step(
     Vec<ParamType>,  // next_hidden_state
     Option<Vec<ParamType>>,  // output
 ) {
-    debug_assert_eq!(parameters.len(), PARAM_SIZE);
-    debug_assert_eq!(cell_state.len(), HIDDEN_STATE_DIMENSION);
-    debug_assert_eq!(hidden_state.len(), HIDDEN_STATE_DIMENSION);
-    debug_assert_eq!(input.len(), INPUT_DIMENSION);
-
-    let concat_input = vec![
-        hidden_state.to_vec(),
-        input.to_vec(),
-    ].concat();
-    let mut offset = 0;
-
-    let forget_gate = calc_forget_gate(
-        &parameters[offset..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))],
-        &concat_input,
-        &parameters[(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION)],
+    let mut next_cell_state = cell_state.to_vec();
+    let mut next_hidden_state = hidden_state.to_vec();
+    let mut next_output = if output { Some(vec![0.0; spec.output_dimension]) } else { None };
+
+    time_step_in_place(
+        parameters,
+        &mut next_cell_state,
+        &mut next_hidden_state,
+        input,
+        next_output.as_deref_mut(),
+        &mut Scratch::new(spec),
     );
-    offset += HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION;
 
-    let input_gate = calc_input_gate(
-        &parameters[offset..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))],
-        &concat_input,
-        &parameters[(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION)],
-    );
-    offset += HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION;
-
-    let output_gate = calc_output_gate(
-        &parameters[offset..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))],
-        &concat_input,
-        &parameters[(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION)],
-    );
-    offset += HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION;
+    (next_cell_state, next_hidden_state, next_output)
+}
 
-    let activation = calc_activation(
-        &parameters[offset..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))],
-        &concat_input,
-        &parameters[(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION))..(offset + HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION)],
-    );
-    offset += HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + HIDDEN_STATE_DIMENSION;
+// `time_step` without allocations: c_(t-1) and h_(t-1) become c_t and h_t
+// The spec is the one that `scratch` is created with.
+pub fn time_step_in_place(
+    parameters: &[ParamType],
+    cell_state: &mut [ParamType],    // c_(t-1) -> c_t
+    hidden_state: &mut [ParamType],  // h_(t-1) -> h_t
+    input: &[ParamType],   // x_t
+    output: Option<&mut [ParamType]>,
+    scratch: &mut Scratch,
+) {
+    let spec = scratch.spec;
+    let hidden = spec.hidden_state_dimension;
+    assert_eq!(parameters.len(), spec.param_size(), "the parameters don't match the LSTM");
+    debug_assert_eq!(cell_state.len(), hidden);
+    debug_assert_eq!(hidden_state.len(), hidden);
+    debug_assert_eq!(input.len(), spec.input_dimension);
+
+    scratch.concat_input[..hidden].copy_from_slice(hidden_state);
+    scratch.concat_input[hidden..].copy_from_slice(input);
+
+    for (index, gate) in [
+        &mut scratch.forget_gate,
+        &mut scratch.input_gate,
+        &mut scratch.output_gate,
+        &mut scratch.activation,
+    ].into_iter().enumerate() {
+        calc_gate(
+            &parameters[scratch.layout.gate_weights[index].clone()],
+            &scratch.concat_input,
+            &parameters[scratch.layout.gate_biases[index].clone()],
+            gate,
+            spec.gate_activations[index],
+        );
+    }
 
-    let next_cell_state = calc_next_cell_state(
-        &forget_gate,
+    calc_next_cell_state(
+        &scratch.forget_gate,
         cell_state,
-        &input_gate,
-        &activation,
+        &scratch.input_gate,
+        &scratch.activation,
     );
 
-    let next_hidden_state = calc_hidden_state(
-        &output_gate,
-        &next_cell_state,
+    calc_hidden_state(
+        &scratch.output_gate,
+        cell_state,
+        hidden_state,
     );
 
-    let output = if output {
-        let concat_state = vec![
-            next_cell_state.clone(),
-            next_hidden_state.clone(),
-        ].concat();
-
-        Some(calc_output(
-            &parameters[offset..(offset + HIDDEN_STATE_DIMENSION * 2 * OUTPUT_DIMENSION)],
-            &concat_state,
-            &parameters[(offset + HIDDEN_STATE_DIMENSION * 2 * OUTPUT_DIMENSION)..],
-        ))
-    } else {
-        None
-    };
-
-    (next_cell_state, next_hidden_state, output)
+    if let Some(output) = output {
+        scratch.concat_state[..hidden].copy_from_slice(cell_state);
+        scratch.concat_state[hidden..].copy_from_slice(hidden_state);
+
+        calc_output(
+            &parameters[scratch.layout.output_weight.clone()],
+            &scratch.concat_state,
+            &parameters[scratch.layout.output_bias.clone()],
+            output,
+            spec.output_head,
+        );
+    }
 }
 
-pub fn one_hot_encode(byte: u8) -> Vec<ParamType> {
-    assert_eq!(INPUT_DIMENSION, 256);
-    let mut result = vec![0.0; 256];
-    result[byte as usize] = 1.0;
+// an input of `dimension` elements (`LstmSpec.input_dimension`)
+pub fn one_hot_encode(index: usize, dimension: usize) -> Vec<ParamType> {
+    assert!(index < dimension, "{index} is out of the {dimension} dimensions of a one-hot vector");
+    let mut result = vec![0.0; dimension];
+    result[index] = 1.0;
 
     result
 }
 
-pub fn one_hot_decode(token: &[ParamType]) -> u8 {
-    assert_eq!(token.len(), 256);
+// the index of the largest element of an output (`LstmSpec.output_dimension`)
+pub fn one_hot_decode(token: &[ParamType]) -> usize {
     let mut curr_max = ParamType::MIN;
     let mut curr_max_index = 0;
 
@@ -109,132 +301,220 @@ pub fn one_hot_decode(token: &[ParamType]) -> u8 {
         }
     }
 
-    curr_max_index as u8
+    curr_max_index
 }
 
-// f_t: HIDDEN_STATE_DIMENSION
-// i_t: HIDDEN_STATE_DIMENSION
-// o_t: HIDDEN_STATE_DIMENSION
-// g_t: HIDDEN_STATE_DIMENSION
-// c_t: HIDDEN_STATE_DIMENSION
-// h_t: HIDDEN_STATE_DIMENSION
+// f_t: hidden_state_dimension
+// i_t: hidden_state_dimension
+// o_t: hidden_state_dimension
+// g_t: hidden_state_dimension
+// c_t: hidden_state_dimension
+// h_t: hidden_state_dimension
 
 // not in the original LSTM design, only in my own version
-// Y = σ(W_y × (c_t <> h_t) + b_y)
+// Y = σ(W_y × (c_t <> h_t) + b_y), or softmax(W_y × (c_t <> h_t) + b_y)
 fn calc_output(
     output_weight: &[ParamType],  // W_y
     concat_state: &[ParamType],   // c_t <> h_t
     bias: &[ParamType],           // b_y
-) -> Vec<ParamType> {
-    debug_assert_eq!(output_weight.len(), HIDDEN_STATE_DIMENSION * 2 * OUTPUT_DIMENSION);
-    debug_assert_eq!(concat_state.len(), HIDDEN_STATE_DIMENSION * 2);
-    debug_assert_eq!(bias.len(), OUTPUT_DIMENSION);
-
-    (0..OUTPUT_DIMENSION).map(
-        |index1| sigmoid((0..(HIDDEN_STATE_DIMENSION * 2)).map(
-            |index2| output_weight[index1 * (HIDDEN_STATE_DIMENSION * 2) + index2] * concat_state[index2]
-        ).sum::<ParamType>() + bias[index1])
-    ).collect()
+    output: &mut [ParamType],     // Y
+    head: OutputHead,
+) {
+    debug_assert_eq!(output_weight.len(), concat_state.len() * output.len());
+    debug_assert_eq!(bias.len(), output.len());
+
+    mat_vec_parallel(output_weight, concat_state, bias, output, MAT_VEC_THREADS);
+
+    match head {
+        OutputHead::Sigmoid => {
+            for y in output.iter_mut() {
+                *y = sigmoid(*y);
+            }
+        },
+        OutputHead::Softmax => {
+            // subtracting the max doesn't change the result, but keeps `exp` from overflowing
+            let max = output.iter().copied().fold(ParamType::MIN, ParamType::max);
+
+            for y in output.iter_mut() {
+                *y = (*y - max).exp();
+            }
+
+            let sum = output.iter().sum::<ParamType>();
+
+            for y in output.iter_mut() {
+                *y /= sum;
+            }
+        },
+    }
 }
 
 // h_t = o_t * tanh(c_t)
 fn calc_hidden_state(
     output_gate: &[ParamType],  // o_t
     cell_state: &[ParamType],   // c_t
-) -> Vec<ParamType> {
-    debug_assert_eq!(output_gate.len(), HIDDEN_STATE_DIMENSION);
-    debug_assert_eq!(cell_state.len(), HIDDEN_STATE_DIMENSION);
+    hidden_state: &mut [ParamType],  // h_t
+) {
+    debug_assert_eq!(output_gate.len(), cell_state.len());
+    debug_assert_eq!(hidden_state.len(), cell_state.len());
 
-    (0..HIDDEN_STATE_DIMENSION).map(
-        |index| output_gate[index] * cell_state[index].tanh()
-    ).collect()
+    for ((h, o), c) in hidden_state.iter_mut().zip(output_gate.iter()).zip(cell_state.iter()) {
+        *h = o * c.tanh();
+    }
 }
 
 // c_t = f_t * c_(t-1) + i_t * g_t
 fn calc_next_cell_state(
     forget_gate: &[ParamType],  // f_t
-    previous_cell_state: &[ParamType],  // c_(t-1)
+    cell_state: &mut [ParamType],  // c_(t-1) -> c_t
     input_gate: &[ParamType],   // i_t
     activation: &[ParamType],   // g_t
-) -> Vec<ParamType> {
-    debug_assert_eq!(forget_gate.len(), HIDDEN_STATE_DIMENSION);
-    debug_assert_eq!(previous_cell_state.len(), HIDDEN_STATE_DIMENSION);
-    debug_assert_eq!(input_gate.len(), HIDDEN_STATE_DIMENSION);
-    debug_assert_eq!(activation.len(), HIDDEN_STATE_DIMENSION);
+) {
+    debug_assert_eq!(forget_gate.len(), cell_state.len());
+    debug_assert_eq!(input_gate.len(), cell_state.len());
+    debug_assert_eq!(activation.len(), cell_state.len());
 
-    (0..HIDDEN_STATE_DIMENSION).map(
-        |index| forget_gate[index] * previous_cell_state[index] + input_gate[index] * activation[index]
-    ).collect()
+    for index in 0..cell_state.len() {
+        cell_state[index] = forget_gate[index] * cell_state[index] + input_gate[index] * activation[index];
+    }
 }
 
+// Every gate is the same mat-vec, with its own weight, bias and activation (`LstmSpec.gate_activations`).
+// With `DEFAULT_GATE_ACTIVATIONS`,
 // f_t = σ(W_f × (h_(t-1) <> x_t) + b_f)
-fn calc_forget_gate(
-    forget_gate_weight: &[ParamType],  // W_f
+// i_t = σ(W_i × (h_(t-1) <> x_t) + b_i)
+// o_t = σ(W_o × (h_(t-1) <> x_t) + b_o)
+// g_t = tanh(W_g × (h_(t-1) <> x_t) + b_g)
+fn calc_gate(
+    gate_weight: &[ParamType],   // W_f, W_i, W_o or W_g
     concat_input: &[ParamType],  // h_(t-1) <> x_t
-    bias: &[ParamType],  // b_f
-) -> Vec<ParamType> {
-    debug_assert_eq!(forget_gate_weight.len(), HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION));
-    debug_assert_eq!(concat_input.len(), HIDDEN_STATE_DIMENSION + INPUT_DIMENSION);
-    debug_assert_eq!(bias.len(), HIDDEN_STATE_DIMENSION);
+    bias: &[ParamType],          // b_f, b_i, b_o or b_g
+    gate: &mut [ParamType],      // f_t, i_t, o_t or g_t
+    activation: Activation,
+) {
+    debug_assert_eq!(gate_weight.len(), concat_input.len() * gate.len());
+    debug_assert_eq!(bias.len(), gate.len());
 
-    (0..HIDDEN_STATE_DIMENSION).map(
-        |index1| sigmoid((0..(HIDDEN_STATE_DIMENSION + INPUT_DIMENSION)).map(
-            |index2| forget_gate_weight[index1 * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + index2] * concat_input[index2]
-        ).sum::<ParamType>() + bias[index1])
-    ).collect()
-}
+    mat_vec_parallel(gate_weight, concat_input, bias, gate, MAT_VEC_THREADS);
 
-// i_t = σ(W_i × (h_(t-1) <> x_t) + b_i)
-fn calc_input_gate(
-    input_gate_weight: &[ParamType],  // W_i
-    concat_input: &[ParamType],  // h_(t-1) <> x_t
-    bias: &[ParamType],  // b_i
-) -> Vec<ParamType> {
-    debug_assert_eq!(input_gate_weight.len(), HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION));
-    debug_assert_eq!(concat_input.len(), HIDDEN_STATE_DIMENSION + INPUT_DIMENSION);
-    debug_assert_eq!(bias.len(), HIDDEN_STATE_DIMENSION);
-
-    (0..HIDDEN_STATE_DIMENSION).map(
-        |index1| sigmoid((0..(HIDDEN_STATE_DIMENSION + INPUT_DIMENSION)).map(
-            |index2| input_gate_weight[index1 * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + index2] * concat_input[index2]
-        ).sum::<ParamType>() + bias[index1])
-    ).collect()
-}
-
-// o_t = σ(W_i × (h_(t-1) <> x_t) + b_o)
-fn calc_output_gate(
-    output_gate_weight: &[ParamType],  // W_o
-    concat_input: &[ParamType],  // h_(t-1) <> x_t
-    bias: &[ParamType],  // b_o
-) -> Vec<ParamType> {
-    debug_assert_eq!(output_gate_weight.len(), HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION));
-    debug_assert_eq!(concat_input.len(), HIDDEN_STATE_DIMENSION + INPUT_DIMENSION);
-    debug_assert_eq!(bias.len(), HIDDEN_STATE_DIMENSION);
-
-    (0..HIDDEN_STATE_DIMENSION).map(
-        |index1| sigmoid((0..(HIDDEN_STATE_DIMENSION + INPUT_DIMENSION)).map(
-            |index2| output_gate_weight[index1 * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + index2] * concat_input[index2]
-        ).sum::<ParamType>() + bias[index1])
-    ).collect()
-}
-
-// g_t = σ(W_g × (h_(t-1) <> x_t) + b_g)
-fn calc_activation(
-    activation_weight: &[ParamType],  // W_g
-    concat_input: &[ParamType],       // h_(t-1) <> x_t
-    bias: &[ParamType],  // b_g
-) -> Vec<ParamType> {
-    debug_assert_eq!(activation_weight.len(), HIDDEN_STATE_DIMENSION * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION));
-    debug_assert_eq!(concat_input.len(), HIDDEN_STATE_DIMENSION + INPUT_DIMENSION);
-    debug_assert_eq!(bias.len(), HIDDEN_STATE_DIMENSION);
-
-    (0..HIDDEN_STATE_DIMENSION).map(
-        |index1| sigmoid((0..(HIDDEN_STATE_DIMENSION + INPUT_DIMENSION)).map(
-            |index2| activation_weight[index1 * (HIDDEN_STATE_DIMENSION + INPUT_DIMENSION) + index2] * concat_input[index2]
-        ).sum::<ParamType>() + bias[index1])
-    ).collect()
+    for g in gate.iter_mut() {
+        *g = activation.apply(*g);
+    }
 }
 
 fn sigmoid(x: ParamType) -> ParamType {
     1.0 / (1.0 + (-x).exp())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_covers_every_parameter() {
+        let spec = LstmSpec::new(3, 5, 2);
+        let layout = spec.layout();
+        let mut ranges = layout.gate_weights.iter().chain(layout.gate_biases.iter()).cloned().collect::<Vec<_>>();
+        ranges.push(layout.output_weight.clone());
+        ranges.push(layout.output_bias.clone());
+        ranges.sort_by_key(|range| range.start);
+
+        assert_eq!(ranges[0].start, 0);
+        assert!(ranges.windows(2).all(|w| w[0].end == w[1].start));
+        assert_eq!(spec.param_size(), (5 * 8 + 5) * 4 + 5 * 2 * 2 + 2);
+        assert_eq!(layout.gate_weights[1], 45..85);
+        assert_eq!(spec.parameter_groups().len(), 10);
+
+        // W_i has 8 inputs per row, and W_y has 10
+        let scales = spec.scales();
+        assert_eq!(scales.len(), spec.param_size());
+        assert_eq!(scales[45], 1.0 / 8f64.sqrt());
+        assert_eq!(scales[layout.gate_biases[1].start], 1.0);
+        assert_eq!(scales[layout.output_weight.start], 1.0 / 10f64.sqrt());
+        assert_eq!(scales[spec.param_size() - 1], 1.0);
+
+        // the default is the old hard-coded one
+        assert_eq!(LstmSpec::default().param_size(), 1024 * 2 * 256 + 256 + (1024 * (1024 + 256) + 1024) * 4);
+        assert_eq!(LstmSpec::default(), LstmSpec::new(256, 1024, 256));
+        assert!(LstmSpec::new(3, 0, 2).validate().is_err());
+    }
+
+    #[test]
+    fn small_lstm_time_step() {
+        let spec = LstmSpec::new(3, 5, 2);
+        let parameters = (0..spec.param_size()).map(|i| ((i % 7) as f32 - 3.0) * 0.1).collect::<Vec<_>>();
+        let (cell_state, hidden_state, output) = time_step(&spec, &parameters, &[0.0; 5], &[0.0; 5], &[1.0, 0.0, -1.0], true);
+
+        assert_eq!(cell_state.len(), 5);
+        assert_eq!(hidden_state.len(), 5);
+        assert_eq!(output.as_ref().map(|y| y.len()), Some(2));
+        assert!(output.unwrap().iter().all(|y| 0.0 < *y && *y < 1.0));
+    }
+
+    // a cell with 1 input, 1 hidden state and 2 outputs
+    fn reference_parameters() -> Vec<ParamType> {
+        vec![
+            0.5, 1.0, 0.0,   // W_f, b_f
+            0.0, 2.0, -1.0,  // W_i, b_i
+            1.0, 0.0, 0.5,   // W_o, b_o
+            0.0, 1.0, 0.0,   // W_g, b_g
+            1.0, -1.0,       // W_y
+            0.5, 2.0,
+            0.0, 0.1,        // b_y
+        ]
+    }
+
+    fn assert_close(a: &[ParamType], b: &[ParamType]) {
+        assert_eq!(a.len(), b.len());
+        assert!(a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-5), "{a:?} != {b:?}");
+    }
+
+    // c_(t-1) = 0.5, h_(t-1) = 0.25, x_t = 1
+    //
+    // f_t = σ(0.5 * 0.25 + 1 * 1 + 0) = σ(1.125)
+    // i_t = σ(0 * 0.25 + 2 * 1 - 1) = σ(1)
+    // o_t = σ(1 * 0.25 + 0 * 1 + 0.5) = σ(0.75)
+    // g_t = tanh(0 * 0.25 + 1 * 1 + 0) = tanh(1)
+    // c_t = f_t * 0.5 + i_t * g_t = 0.934227
+    // h_t = o_t * tanh(c_t) = 0.497538
+    // W_y × (c_t <> h_t) + b_y = [c_t - h_t, 0.5 * c_t + 2 * h_t + 0.1] = [0.436689, 1.562190]
+    #[test]
+    fn matches_reference_cell() {
+        let parameters = reference_parameters();
+        let spec = LstmSpec::new(1, 1, 2);
+        assert_eq!(spec.param_size(), parameters.len());
+
+        let (cell_state, hidden_state, output) = time_step(&spec, &parameters, &[0.5], &[0.25], &[1.0], true);
+        assert_close(&cell_state, &[0.934227]);
+        assert_close(&hidden_state, &[0.497538]);
+        assert_close(&output.unwrap(), &[0.607470, 0.826667]);
+
+        let softmax = LstmSpec { output_head: OutputHead::Softmax, ..spec };
+        let (_, _, output) = time_step(&softmax, &parameters, &[0.5], &[0.25], &[1.0], true);
+        assert_close(&output.unwrap(), &[0.244992, 0.755008]);
+
+        // g_t = σ(1), like the first version of this sample
+        let sigmoid_g = LstmSpec { gate_activations: [Activation::Sigmoid; 4], ..spec };
+        let (cell_state, _, _) = time_step(&sigmoid_g, &parameters, &[0.5], &[0.25], &[1.0], false);
+        assert_close(&cell_state, &[0.911904]);
+    }
+
+    #[test]
+    fn one_hot() {
+        assert_eq!(one_hot_encode(2, 4), vec![0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(one_hot_decode(&[0.1, 0.7, 0.2]), 1);
+        assert_eq!(one_hot_decode(&one_hot_encode(300, 512)), 300);
+    }
+
+    #[test]
+    fn softmax_is_a_distribution() {
+        let spec = LstmSpec { output_head: OutputHead::Softmax, ..LstmSpec::new(3, 5, 7) };
+
+        // big enough to overflow `exp` without the max trick
+        let parameters = (0..spec.param_size()).map(|i| ((i % 5) as f32 - 2.0) * 40.0).collect::<Vec<_>>();
+        let (_, _, output) = time_step(&spec, &parameters, &[0.0; 5], &[0.0; 5], &[1.0, 0.5, -1.0], true);
+        let output = output.unwrap();
+
+        assert!(output.iter().all(|y| y.is_finite() && *y >= 0.0));
+        assert!((output.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,320 @@
+
+// A byte-level language model: `samples::lstm` reads a text corpus a byte at a time, and predicts the next byte.
+// To train it, set `Config.objective` to `Objective::NativeWithDimension { f: samples::text::f, dimension: samples::text::dimension }`.
+// `SPEC.parameter_groups()` is a good start for `Config.parameter_groups`, and `SPEC.scales()` for `Scaling::Manual`.
+//
+// Each evaluation runs the LSTM over a mini-batch: `BATCH_SIZE` windows of `SEQUENCE_LENGTH + 1` bytes.
+// For each window, the state starts at zero, and the LSTM predicts bytes 1..=SEQUENCE_LENGTH from the bytes before them.
+// The loss is the average over every prediction of the batch.
+//
+// The batch changes every `EVALUATIONS_PER_BATCH` calls, so the losses of different batches are not
+// comparable: a state that got a lucky batch keeps its loss. Use `usize::MAX` to train on a single batch.
+//
+// The calls are counted per process, and the workers of a process share the count, so which call gets
+// which batch depends on the order the workers call `f`, and every remote worker starts from the first batch.
+// A run is only reproducible with 1 worker (or a single batch).
+
+use super::lstm::{self, LstmSpec, OutputHead, Scratch, DEFAULT_GATE_ACTIVATIONS};
+use crate::config::Config;
+use crate::files::read_bytes;
+use crate::scaling::Scaling;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+// `Objective::Native` runs in `f32`
+type ParamType = f32;
+
+// configure the constants below to train your model
+const CORPUS_PATH: &str = "./corpus.txt";
+pub const SPEC: LstmSpec = LstmSpec {
+    input_dimension: 256,
+    hidden_state_dimension: 16,
+    output_dimension: 256,
+    gate_activations: DEFAULT_GATE_ACTIVATIONS,
+    output_head: OutputHead::Softmax,
+};
+const SEQUENCE_LENGTH: usize = 32;
+const BATCH_SIZE: usize = 4;
+const EVALUATIONS_PER_BATCH: usize = 256;
+const LOSS: Loss = Loss::CrossEntropy;
+
+// the windows of each batch are drawn from this seed, so the `n`-th batch is the same in every run and every process
+// (but which calls get it is not: see above)
+const BATCH_SEED: u64 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Loss {
+    // -ln(p) of the next byte, where p is the output of the LSTM normalized to sum to 1
+    // With `OutputHead::Softmax`, the output is already p.
+    CrossEntropy,
+
+    // the ratio of the predictions whose `one_hot_decode` is not the next byte
+    // `LOSS` can pick it, but it's flat almost everywhere, so it's for measuring rather than training
+    #[allow(dead_code)]
+    NextByteError,
+}
+
+pub struct TextObjective {
+    corpus: Vec<u8>,
+    spec: LstmSpec,
+    sequence_length: usize,
+    batch_size: usize,
+    loss: Loss,
+    seed: u64,
+}
+
+impl TextObjective {
+    pub fn new(
+        corpus: Vec<u8>,
+        spec: LstmSpec,
+        sequence_length: usize,
+        batch_size: usize,
+        loss: Loss,
+        seed: u64,
+    ) -> Result<Self, String> {
+        spec.validate()?;
+
+        if spec.input_dimension != 256 || spec.output_dimension != 256 {
+            return Err(format!("a byte-level model has 256 inputs and 256 outputs: {spec:?}"));
+        }
+
+        if sequence_length == 0 || batch_size == 0 {
+            return Err(String::from("the sequence length and the batch size have to be positive"));
+        }
+
+        if corpus.len() < sequence_length + 1 {
+            return Err(format!(
+                "the corpus has {} bytes, but a sequence needs {} bytes",
+                corpus.len(),
+                sequence_length + 1,
+            ));
+        }
+
+        Ok(TextObjective { corpus, spec, sequence_length, batch_size, loss, seed })
+    }
+
+    pub fn from_file(
+        path: &str,
+        spec: LstmSpec,
+        sequence_length: usize,
+        batch_size: usize,
+        loss: Loss,
+        seed: u64,
+    ) -> Result<Self, String> {
+        let corpus = read_bytes(path).map_err(|e| e.render_error())?;
+
+        TextObjective::new(corpus, spec, sequence_length, batch_size, loss, seed)
+    }
+
+    // the start of each window of the `index`-th batch
+    pub fn batch(&self, index: usize) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let last_start = self.corpus.len() - self.sequence_length - 1;
+
+        (0..self.batch_size).map(|_| rng.gen_range(0..=last_start)).collect()
+    }
+
+    pub fn loss(&self, parameters: &[ParamType], batch_index: usize) -> ParamType {
+        assert_eq!(parameters.len(), self.spec.param_size(), "`Config.dimension` has to be `SPEC.param_size()` or None");
+        let mut scratch = Scratch::new(&self.spec);
+        let mut cell_state = vec![0.0; self.spec.hidden_state_dimension];
+        let mut hidden_state = vec![0.0; self.spec.hidden_state_dimension];
+        let mut input = vec![0.0; self.spec.input_dimension];
+        let mut output = vec![0.0; self.spec.output_dimension];
+        let mut sum = 0.0;
+
+        for start in self.batch(batch_index) {
+            cell_state.fill(0.0);
+            hidden_state.fill(0.0);
+
+            for window in self.corpus[start..(start + self.sequence_length + 1)].windows(2) {
+                input.fill(0.0);
+                input[window[0] as usize] = 1.0;
+
+                lstm::time_step_in_place(parameters, &mut cell_state, &mut hidden_state, &input, Some(&mut output), &mut scratch);
+                sum += self.prediction_loss(&output, window[1]);
+            }
+        }
+
+        sum / (self.batch_size * self.sequence_length) as ParamType
+    }
+
+    fn prediction_loss(&self, output: &[ParamType], next_byte: u8) -> ParamType {
+        match self.loss {
+            Loss::CrossEntropy => {
+                let p = output[next_byte as usize] / output.iter().sum::<ParamType>();
+
+                // `ParamType::MIN_POSITIVE` keeps it finite when the sigmoid underflows
+                -p.max(ParamType::MIN_POSITIVE).ln()
+            },
+            Loss::NextByteError => if lstm::one_hot_decode(output) == next_byte as usize { 0.0 } else { 1.0 },
+        }
+    }
+}
+
+static OBJECTIVE: OnceLock<TextObjective> = OnceLock::new();
+// the calls to `f` in this process, by every worker (it picks the batch: see the top of the file)
+static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+// the number of parameters of `f`, as `Objective::NativeWithDimension`
+pub fn dimension() -> usize {
+    SPEC.param_size()
+}
+
+// the objective with the constants above, as `Objective::NativeWithDimension`
+// It reads the corpus at the first call, and panics if it cannot.
+pub fn f(parameters: &[ParamType]) -> ParamType {
+    let objective = OBJECTIVE.get_or_init(
+        || match TextObjective::from_file(CORPUS_PATH, SPEC, SEQUENCE_LENGTH, BATCH_SIZE, LOSS, BATCH_SEED) {
+            Ok(objective) => objective,
+            Err(e) => panic!("cannot load the corpus at `{CORPUS_PATH}`: {e}"),
+        }
+    );
+    let batch_index = EVALUATIONS.fetch_add(1, Ordering::Relaxed) / EVALUATIONS_PER_BATCH;
+
+    objective.loss(parameters, batch_index)
+}
+
+// `nonlinear_opt run text` calls it with `default_config()`: it fills what `config.rs` leaves at the defaults
+pub fn configure(config: &mut Config) {
+    if config.scaling == Scaling::Isotropic {
+        config.scaling = Scaling::Manual(SPEC.scales());
+    }
+
+    // the visualizer prints a line per gate
+    if config.parameter_groups.is_empty() {
+        config.parameter_groups = SPEC.parameter_groups();
+    }
+}
+
+// Runs the model over `prompt`, and then feeds each generated byte back for `length` more bytes.
+// A `temperature` of 0 always picks the most likely byte (`one_hot_decode`). Otherwise a byte is drawn with
+// the probability of `Loss::CrossEntropy`, sharpened (< 1) or flattened (> 1) by the temperature.
+pub fn generate<R: Rng, F: FnMut(u8)>(
+    spec: &LstmSpec,
+    parameters: &[ParamType],
+    prompt: &[u8],
+    length: usize,
+    temperature: ParamType,
+    rng: &mut R,
+    mut emit: F,
+) {
+    assert!(!prompt.is_empty(), "the prompt has to have at least one byte");
+    assert!(spec.input_dimension == 256 && spec.output_dimension == 256, "a byte-level model has 256 inputs and 256 outputs: {spec:?}");
+    let mut scratch = Scratch::new(spec);
+    let mut cell_state = vec![0.0; spec.hidden_state_dimension];
+    let mut hidden_state = vec![0.0; spec.hidden_state_dimension];
+    let mut output = vec![0.0; spec.output_dimension];
+    let mut next_byte = prompt[0];
+
+    for index in 0..(prompt.len() - 1 + length) {
+        let input = lstm::one_hot_encode(next_byte as usize, spec.input_dimension);
+        lstm::time_step_in_place(parameters, &mut cell_state, &mut hidden_state, &input, Some(&mut output), &mut scratch);
+
+        if index + 1 < prompt.len() {
+            next_byte = prompt[index + 1];
+        }
+
+        else {
+            next_byte = sample_byte(&output, temperature, rng);
+            emit(next_byte);
+        }
+    }
+}
+
+fn sample_byte<R: Rng>(output: &[ParamType], temperature: ParamType, rng: &mut R) -> u8 {
+    if temperature <= 0.0 {
+        return lstm::one_hot_decode(output) as u8;
+    }
+
+    // p ∝ y^(1 / temperature), computed in the log space so that a small temperature doesn't underflow
+    let logits = output.iter().map(|y| y.max(ParamType::MIN_POSITIVE).ln() / temperature).collect::<Vec<_>>();
+    let max = logits.iter().copied().fold(ParamType::MIN, ParamType::max);
+    let weights = logits.iter().map(|l| (l - max).exp()).collect::<Vec<_>>();
+    let mut r = rng.gen::<ParamType>() * weights.iter().sum::<ParamType>();
+
+    for (byte, weight) in weights.iter().enumerate() {
+        if r < *weight {
+            return byte as u8;
+        }
+
+        r -= weight;
+    }
+
+    // rounding errors
+    lstm::one_hot_decode(output) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objective(loss: Loss) -> TextObjective {
+        TextObjective::new(
+            b"hello, world! ".repeat(8),
+            LstmSpec::new(256, 4, 256),
+            8,
+            3,
+            loss,
+            0,
+        ).unwrap()
+    }
+
+    #[test]
+    fn batches() {
+        let objective = objective(Loss::CrossEntropy);
+
+        assert_eq!(objective.batch(3), objective.batch(3));
+        assert_ne!(objective.batch(3), objective.batch(4));
+        assert!((0..100).flat_map(|index| objective.batch(index)).all(|start| start + 9 <= 14 * 8));
+
+        assert!(TextObjective::new(b"short".to_vec(), LstmSpec::new(256, 4, 256), 8, 3, Loss::CrossEntropy, 0).is_err());
+        assert!(TextObjective::new(b"hello, world!".to_vec(), LstmSpec::new(8, 4, 256), 8, 3, Loss::CrossEntropy, 0).is_err());
+    }
+
+    #[test]
+    fn losses_of_zero_parameters() {
+        let parameters = vec![0.0; LstmSpec::new(256, 4, 256).param_size()];
+
+        // every output is σ(0), so every byte is equally likely
+        let cross_entropy = objective(Loss::CrossEntropy).loss(&parameters, 0);
+        assert!((cross_entropy - 256f32.ln()).abs() < 1e-4);
+
+        // every prediction is byte 0
+        assert_eq!(objective(Loss::NextByteError).loss(&parameters, 0), 1.0);
+    }
+
+    #[test]
+    fn bias_toward_a_byte() {
+        let spec = LstmSpec::new(256, 4, 256);
+        let mut parameters = vec![0.0; spec.param_size()];
+
+        // b_y prefers 'l', and every window of 8 bytes has an 'l'
+        parameters[spec.layout().output_bias.start + b'l' as usize] = 1.0;
+
+        let error = objective(Loss::NextByteError).loss(&parameters, 0);
+        assert!(0.0 < error && error < 1.0);
+    }
+
+    #[test]
+    fn generation() {
+        let spec = LstmSpec::new(256, 4, 256);
+        let mut parameters = vec![0.0; spec.param_size()];
+        parameters[spec.layout().output_bias.start + b'l' as usize] = 1.0;
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut greedy = vec![];
+        generate(&spec, &parameters, b"he", 5, 0.0, &mut rng, |byte| greedy.push(byte));
+        assert_eq!(greedy, b"lllll");
+
+        // a low temperature is almost greedy, and a high one is almost uniform
+        let mut cold = vec![];
+        generate(&spec, &parameters, b"he", 100, 0.01, &mut rng, |byte| cold.push(byte));
+        assert!(cold.iter().all(|byte| *byte == b'l'));
+
+        let mut hot = vec![];
+        generate(&spec, &parameters, b"he", 100, 100.0, &mut rng, |byte| hot.push(byte));
+        assert!(hot.iter().filter(|byte| **byte != b'l').count() > 90);
+    }
+}
@@ -0,0 +1,248 @@
+// How the workers draw candidates in the random phase (`MessageFromMain::TryRandomParams`).
+//
+// The master splits the candidates among the workers with `Partition`. Latin hypercube and Halton
+// give each worker a disjoint share of one big design, so the workers don't cover the same region twice.
+// The other strategies draw independent samples, and the workers' rngs are already independent.
+
+use crate::float::Float;
+use crate::utils::generate_random_params;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InitStrategy {
+    // a uniform vector in [-0.5, 0.5]^n, rescaled to a random l2 norm (`Config.initial_l2_norm` * 0.5 ~ 1.5)
+    RandomL2Norm,
+
+    // each parameter is uniform in [lower, upper]
+    Uniform { lower: f64, upper: f64 },
+
+    // each parameter is normal, with mean 0
+    Gaussian { scale: f64 },
+
+    // every parameter is stratified in [lower, upper]: each of the `num_workers * count` strata
+    // of every parameter has exactly one candidate
+    LatinHypercube { lower: f64, upper: f64 },
+
+    // a Halton sequence in [lower, upper], with a random shift
+    // it's a low-discrepancy sequence, like Sobol, but doesn't need a table of direction numbers
+    Halton { lower: f64, upper: f64 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Partition {
+    // 0-based index of the worker, and the number of workers
+    pub index: usize,
+    pub total: usize,
+
+    // every worker gets the same seed, so that they agree on the shared part of the design
+    pub seed: u64,
+}
+
+pub struct InitSampler<T: Float> {
+    strategy: InitStrategy,
+    param_l2_norm: T,
+    dimension: usize,
+
+    // the number of candidates drawn so far
+    drawn: usize,
+
+    // LatinHypercube: strata[d][i] is the global stratum of the i-th candidate in the d-th parameter
+    strata: Vec<Vec<usize>>,
+    total_strata: usize,
+
+    // Halton: the first index of this worker, the bases and the shifts
+    first_index: usize,
+    primes: Vec<u64>,
+    shifts: Vec<f64>,
+}
+
+impl<T: Float> InitSampler<T> {
+    // `count` is the number of candidates that this worker draws. It may draw more, but then
+    // the Latin hypercube loses its stratification.
+    pub fn new<R: Rng>(
+        strategy: &InitStrategy,
+        param_l2_norm: T,
+        dimension: usize,
+        count: usize,
+        partition: &Partition,
+        rng: &mut R,
+    ) -> Self {
+        let mut shared_rng = StdRng::seed_from_u64(partition.seed);
+        let mut sampler = InitSampler {
+            strategy: strategy.clone(),
+            param_l2_norm,
+            dimension,
+            drawn: 0,
+            strata: vec![],
+            total_strata: count * partition.total,
+            first_index: partition.index * count,
+            primes: vec![],
+            shifts: vec![],
+        };
+
+        match strategy {
+            InitStrategy::LatinHypercube { .. } => {
+                // In each parameter, the workers take a random block of `count` strata,
+                // and this worker shuffles the strata in its block.
+                sampler.strata = (0..dimension).map(
+                    |_| {
+                        let mut blocks = (0..partition.total).collect::<Vec<_>>();
+                        blocks.shuffle(&mut shared_rng);
+                        let block = blocks[partition.index];
+                        let mut strata = ((block * count)..((block + 1) * count)).collect::<Vec<_>>();
+                        strata.shuffle(rng);
+
+                        strata
+                    }
+                ).collect();
+            },
+            InitStrategy::Halton { .. } => {
+                sampler.primes = first_primes(dimension);
+                sampler.shifts = (0..dimension).map(|_| shared_rng.gen::<f64>()).collect();
+            },
+            _ => {},
+        }
+
+        sampler
+    }
+
+    pub fn next<R: Rng>(&mut self, rng: &mut R) -> Vec<T> {
+        let index = self.drawn;
+        self.drawn += 1;
+
+        match &self.strategy {
+            InitStrategy::RandomL2Norm => {
+                let l2_norm = self.param_l2_norm * (T::random(rng) + T::from_f64(0.5));
+
+                generate_random_params(rng, self.dimension, l2_norm)
+            },
+            InitStrategy::Uniform { lower, upper } => (0..self.dimension).map(
+                |_| T::from_f64(lower + (upper - lower) * rng.gen::<f64>())
+            ).collect(),
+            InitStrategy::Gaussian { scale } => (0..self.dimension).map(
+                |_| T::from_f64(scale * standard_normal(rng))
+            ).collect(),
+            InitStrategy::LatinHypercube { lower, upper } => (0..self.dimension).map(
+                |d| {
+                    // falls back to a uniform sample after `count` candidates
+                    let stratum = self.strata[d].get(index).copied().unwrap_or_else(|| rng.gen_range(0..self.total_strata));
+                    let unit = (stratum as f64 + rng.gen::<f64>()) / self.total_strata as f64;
+
+                    T::from_f64(lower + (upper - lower) * unit)
+                }
+            ).collect(),
+            InitStrategy::Halton { lower, upper } => {
+                // index 0 of a Halton sequence is the origin in every dimension
+                let halton_index = (self.first_index + index + 1) as u64;
+
+                self.primes.iter().zip(self.shifts.iter()).map(
+                    |(prime, shift)| {
+                        let unit = (radical_inverse(*prime, halton_index) + shift).fract();
+
+                        T::from_f64(lower + (upper - lower) * unit)
+                    }
+                ).collect()
+            },
+        }
+    }
+}
+
+// Box-Muller
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    // 1 - [0, 1) is never 0, so `ln` is finite
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+// the digits of `n` in base `base`, mirrored around the decimal point
+fn radical_inverse(base: u64, mut n: u64) -> f64 {
+    let mut result = 0.0;
+    let mut scale = 1.0 / base as f64;
+
+    while n > 0 {
+        result += (n % base) as f64 * scale;
+        n /= base;
+        scale /= base as f64;
+    }
+
+    result
+}
+
+fn first_primes(n: usize) -> Vec<u64> {
+    let mut primes = Vec::with_capacity(n);
+    let mut candidate = 2;
+
+    while primes.len() < n {
+        if primes.iter().take_while(|p| *p * *p <= candidate).all(|p| candidate % p != 0) {
+            primes.push(candidate);
+        }
+
+        candidate += 1;
+    }
+
+    primes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw_all(strategy: &InitStrategy, dimension: usize, count: usize, total: usize) -> Vec<Vec<f64>> {
+        (0..total).flat_map(
+            |index| {
+                let mut rng = StdRng::seed_from_u64(100 + index as u64);
+                let partition = Partition { index, total, seed: 7 };
+                let mut sampler = InitSampler::<f64>::new(strategy, 1.0, dimension, count, &partition, &mut rng);
+
+                (0..count).map(|_| sampler.next(&mut rng)).collect::<Vec<_>>()
+            }
+        ).collect()
+    }
+
+    #[test]
+    fn latin_hypercube_covers_every_stratum() {
+        let (dimension, count, total) = (5, 8, 4);
+        let points = draw_all(&InitStrategy::LatinHypercube { lower: -2.0, upper: 2.0 }, dimension, count, total);
+
+        for d in 0..dimension {
+            let mut strata = points.iter().map(
+                |point| ((point[d] + 2.0) / 4.0 * (count * total) as f64) as usize
+            ).collect::<Vec<_>>();
+            strata.sort();
+
+            assert_eq!(strata, (0..(count * total)).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn halton_workers_dont_overlap() {
+        let points = draw_all(&InitStrategy::Halton { lower: 0.0, upper: 1.0 }, 3, 16, 3);
+
+        for (i, p1) in points.iter().enumerate() {
+            assert!(p1.iter().all(|n| (0.0..1.0).contains(n)));
+
+            for p2 in points[(i + 1)..].iter() {
+                assert_ne!(p1, p2);
+            }
+        }
+
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert_eq!(first_primes(6), vec![2, 3, 5, 7, 11, 13]);
+    }
+
+    #[test]
+    fn uniform_and_gaussian() {
+        let uniform = draw_all(&InitStrategy::Uniform { lower: 3.0, upper: 4.0 }, 4, 50, 2);
+        assert!(uniform.iter().flatten().all(|n| (3.0..4.0).contains(n)));
+
+        let gaussian = draw_all(&InitStrategy::Gaussian { scale: 2.0 }, 4, 500, 2);
+        let n = gaussian.len() as f64 * 4.0;
+        let mean = gaussian.iter().flatten().sum::<f64>() / n;
+        let variance = gaussian.iter().flatten().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n;
+
+        assert!(mean.abs() < 0.1);
+        assert!((variance.sqrt() - 2.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,173 @@
+// Per-parameter scales of the random steps (`Config.scaling`).
+//
+// Without scales, a random step is isotropic: every parameter moves about the same distance.
+// That's bad if the parameters differ by orders of magnitude. With scales, `step_size` is measured
+// in the scaled space: the workers multiply each element of the first step of a state by the scale
+// of its parameter. The later steps keep the l2 norm of the previous step (see `multi::event_loop`),
+// and the scales decide how the random part of a step is distributed among the parameters.
+
+use crate::float::Float;
+
+// `Config.scaling` picks one
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scaling {
+    // every parameter has the same scale
+    Isotropic,
+
+    // a scale per parameter. It has to have `dimension` positive elements.
+    Manual(Vec<f64>),
+
+    // The master learns the scales from the accepted steps: the scale of a parameter is the
+    // root of the moving average of its squared steps.
+    // `decay` is 0 ~ 1: the weight of the old average. The scales are normalized to a
+    // root-mean-square of 1, and then clamped to at least `min_scale`, so that no parameter freezes.
+    Learned { decay: f64, min_scale: f64 },
+}
+
+impl Scaling {
+    pub fn validate(&self, dimension: usize) -> Result<(), String> {
+        match self {
+            Scaling::Isotropic => Ok(()),
+            Scaling::Manual(scales) if scales.len() != dimension => Err(format!(
+                "scaling has {} scales, but the objective has {dimension} parameters", scales.len()
+            )),
+            Scaling::Manual(scales) if scales.iter().any(|s| !s.is_finite() || *s <= 0.0) => Err(
+                String::from("every scale of scaling has to be positive")
+            ),
+            Scaling::Manual(_) => Ok(()),
+            Scaling::Learned { decay, .. } if !(0.0..1.0).contains(decay) => Err(
+                String::from("the decay of scaling has to be 0 ~ 1 (excluding 1)")
+            ),
+            Scaling::Learned { min_scale, .. } if *min_scale < 0.0 => Err(
+                String::from("the min_scale of scaling cannot be negative")
+            ),
+            Scaling::Learned { .. } => Ok(()),
+        }
+    }
+}
+
+// It's owned by the master.
+pub struct Scales {
+    scaling: Scaling,
+
+    // `Scaling::Learned`: moving average of the squared steps
+    // it's empty until the first accepted step
+    mean_squares: Vec<f64>,
+}
+
+impl Scales {
+    pub fn new(scaling: Scaling) -> Self {
+        Scales { scaling, mean_squares: vec![] }
+    }
+
+    // It's called with every accepted step.
+    pub fn observe<T: Float>(&mut self, step: &[T]) {
+        let Scaling::Learned { decay, .. } = self.scaling else { return; };
+
+        if self.mean_squares.is_empty() {
+            self.mean_squares = step.iter().map(|s| s.to_f64() * s.to_f64()).collect();
+        }
+
+        else {
+            for (mean_square, s) in self.mean_squares.iter_mut().zip(step.iter()) {
+                *mean_square = decay * *mean_square + (1.0 - decay) * s.to_f64() * s.to_f64();
+            }
+        }
+    }
+
+    // `observe` with a sparse step (see `subspace.rs`): only the parameters at `indices` moved, by `delta`.
+    // The other parameters were not perturbed, so their averages don't change. Before the first step,
+    // they start at the average of `delta`'s squares, so that their scales are neutral.
+    pub fn observe_sparse<T: Float>(&mut self, dimension: usize, indices: &[usize], delta: &[T]) {
+        let Scaling::Learned { decay, .. } = self.scaling else { return; };
+
+        if self.mean_squares.is_empty() {
+            let mean = delta.iter().map(|d| d.to_f64() * d.to_f64()).sum::<f64>() / delta.len().max(1) as f64;
+            self.mean_squares = vec![mean; dimension];
+
+            for (index, d) in indices.iter().zip(delta.iter()) {
+                self.mean_squares[*index] = d.to_f64() * d.to_f64();
+            }
+        }
+
+        else {
+            for (index, d) in indices.iter().zip(delta.iter()) {
+                let mean_square = &mut self.mean_squares[*index];
+                *mean_square = decay * *mean_square + (1.0 - decay) * d.to_f64() * d.to_f64();
+            }
+        }
+    }
+
+    // None: isotropic
+    pub fn get<T: Float>(&self) -> Option<Vec<T>> {
+        match &self.scaling {
+            Scaling::Isotropic => None,
+            Scaling::Manual(scales) => Some(scales.iter().map(|s| T::from_f64(*s)).collect()),
+            Scaling::Learned { min_scale, .. } => {
+                // no step so far
+                if self.mean_squares.is_empty() {
+                    return None;
+                }
+
+                let rms = (self.mean_squares.iter().sum::<f64>() / self.mean_squares.len() as f64).sqrt();
+
+                // every step was 0
+                if rms == 0.0 {
+                    return None;
+                }
+
+                Some(self.mean_squares.iter().map(
+                    |mean_square| T::from_f64((mean_square.sqrt() / rms).max(*min_scale))
+                ).collect())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learns_from_steps() {
+        let mut scales = Scales::new(Scaling::Learned { decay: 0.5, min_scale: 0.001 });
+        assert_eq!(scales.get::<f64>(), None);
+
+        scales.observe(&[1.0f64, 0.0, 0.001]);
+        scales.observe(&[-1.0f64, 0.0, 0.001]);
+        let learned = scales.get::<f64>().unwrap();
+
+        // the root-mean-square of [1, 0, 0.001] is about 0.577
+        assert!((learned[0] - 3f64.sqrt()).abs() < 1e-3);
+        assert_eq!(learned[1], 0.001);
+        assert!((learned[2] - 3f64.sqrt() * 0.001).abs() < 1e-6);
+    }
+
+    #[test]
+    fn learns_from_sparse_steps() {
+        let mut scales = Scales::new(Scaling::Learned { decay: 0.5, min_scale: 0.001 });
+
+        scales.observe_sparse(4, &[1, 2], &[2.0f64, 2.0]);
+        assert_eq!(scales.get::<f64>().unwrap(), vec![1.0; 4]);
+
+        // the coordinates that didn't move keep their scales, instead of decaying to `min_scale`
+        for _ in 0..20 {
+            scales.observe_sparse(4, &[0], &[4.0f64]);
+        }
+
+        let learned = scales.get::<f64>().unwrap();
+        assert!(learned[0] > learned[1]);
+        assert_eq!(learned[1], learned[2]);
+        assert_eq!(learned[1], learned[3]);
+        assert!(learned[1] > 0.5);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Scaling::Manual(vec![1.0, 2.0]).validate(2).is_ok());
+        assert!(Scaling::Manual(vec![1.0, 2.0]).validate(3).is_err());
+        assert!(Scaling::Manual(vec![1.0, 0.0]).validate(2).is_err());
+        assert!(Scaling::Learned { decay: 1.0, min_scale: 0.1 }.validate(2).is_err());
+        assert!(Scaling::Learned { decay: 0.9, min_scale: 0.1 }.validate(2).is_ok());
+    }
+}
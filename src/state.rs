@@ -1,51 +1,99 @@
-use crate::config::ParamType;
+use crate::float::Float;
+use crate::groups::{pretty_print_group, ParameterGroup};
 use crate::utils::get_l2_norm;
 use h_time::Date;
+use std::sync::Arc;
 
-// TODO: import/export to file
-pub struct State {
+pub struct State<T: Float> {
     pub id: usize,
-    pub parameters: Vec<ParamType>,
-    pub prev_step: Option<Vec<ParamType>>,
-    pub loss: ParamType,
+
+    // immutable, so that the messages to the workers can share them without copying
+    pub parameters: Arc<[T]>,
+    pub prev_step: Option<Arc<[T]>>,
+    pub loss: T,
     pub successful_turns: usize,
     pub failed_turns: usize,
+
+    // the number of calls to `f` spent on this state
+    pub evaluations: usize,
     pub last_updated_at: Option<Date>,
-    pub losses_over_time: Vec<(Date, ParamType)>,
+    pub losses_over_time: Vec<(Date, T)>,
 }
 
-impl State {
+impl<T: Float> State<T> {
+    pub fn new(id: usize, parameters: Vec<T>, prev_step: Option<Vec<T>>, loss: T) -> Self {
+        let now = Date::now();
+
+        State {
+            id,
+            parameters: parameters.into(),
+            prev_step: prev_step.map(|s| s.into()),
+            loss,
+            successful_turns: 0,
+            failed_turns: 0,
+            evaluations: 0,
+            last_updated_at: Some(now),
+            losses_over_time: vec![(now, loss)],
+        }
+    }
+
     pub fn update_best_loss(
         &mut self,
-        new_params: Vec<ParamType>,
-        new_loss: ParamType,
-        prev_step: Vec<ParamType>,
+        new_params: Vec<T>,
+        new_loss: T,
+        prev_step: Vec<T>,
+    ) {
+        self.parameters = new_params.into();
+        self.prev_step = Some(prev_step.into());
+        self.record_update(new_loss);
+    }
+
+    // `update_best_loss` with a sparse step: the new parameters are `base`, and `base[indices[i]] += delta[i]`
+    // `base` is not necessarily the current parameters (see `subspace.rs`).
+    pub fn apply_delta(
+        &mut self,
+        base: &[T],
+        indices: &[usize],
+        delta: &[T],
+        new_loss: T,
     ) {
+        let mut parameters = base.to_vec();
+        let mut prev_step = vec![T::ZERO; base.len()];
+
+        for (index, d) in indices.iter().zip(delta.iter()) {
+            parameters[*index] += *d;
+            prev_step[*index] = *d;
+        }
+
+        self.update_best_loss(parameters, new_loss, prev_step);
+    }
+
+    fn record_update(&mut self, new_loss: T) {
         let now = Date::now();
 
-        self.parameters = new_params;
         self.loss = new_loss;
-        self.prev_step = Some(prev_step);
-        self.last_updated_at = Some(now.clone());
+        self.last_updated_at = Some(now);
         self.successful_turns += 1;
 
         if self.losses_over_time.len() < 64 {
             self.losses_over_time.push((now, new_loss));
         }
 
+        // once it's full, it keeps a checkpoint every 3 minutes and drops the oldest one
         else {
-            let last_check_point = self.losses_over_time.pop().unwrap().0;
+            let last_check_point = self.losses_over_time.last().unwrap().0;
 
             if now.duration_since(&last_check_point).into_minutes() > 3 {
+                self.losses_over_time.remove(0);
                 self.losses_over_time.push((now, new_loss));
-                self.losses_over_time = self.losses_over_time[1..].to_vec();
             }
         }
     }
 
-    pub fn pretty_print(&self) -> String {
+    // `groups` are shown separately, a line per group
+    pub fn pretty_print(&self, groups: &[ParameterGroup]) -> String {
         format!(
-            "id: {}\nparameters: {} (l2_norm: {})\n  gradient: {}\n      loss: {}\nsuccessful turns: {}\nfailed turns: {}\n{}",
+            "id: {}\nparameters: {} (l2_norm: {})\n  gradient: {}\n      loss: {}\n{}successful turns: {}\nfailed turns: {}\nevaluations: {}\n{}",
             self.id,
             pretty_print_vec_float(&self.parameters, false),
             get_l2_norm(&self.parameters),
@@ -57,10 +105,14 @@ impl State {
                 )
             ).unwrap_or_else(|| String::from("None")),
             self.loss,
+            groups.iter().map(
+                |group| format!("  {}\n", pretty_print_group(group, &self.parameters, self.prev_step.as_deref()))
+            ).collect::<String>(),
             self.successful_turns,
             self.failed_turns,
+            self.evaluations,
             if let Some(t) = &self.last_updated_at {
-                format!("last updated {} seconds ago", Date::now().duration_since(&t).into_secs())
+                format!("last updated {} seconds ago", Date::now().duration_since(t).into_secs())
             } else {
                 String::new()
             },
@@ -68,7 +120,7 @@ impl State {
     }
 }
 
-fn pretty_print_vec_float(v: &[ParamType], show_dots: bool) -> String {
+fn pretty_print_vec_float<T: Float>(v: &[T], show_dots: bool) -> String {
     if v.len() > 8 {
         pretty_print_vec_float(&v[..8], true)
     }
@@ -87,7 +139,7 @@ fn pretty_print_vec_float(v: &[ParamType], show_dots: bool) -> String {
     }
 }
 
-fn pretty_print_float(f: ParamType) -> String {
+fn pretty_print_float<T: Float>(f: T) -> String {
     let s = format!("{f:.4}");
 
     if s.len() < 9 {
@@ -101,3 +153,92 @@ fn pretty_print_float(f: ParamType) -> String {
         s
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use h_time::Date;
+
+    fn state_with_history(history: Vec<(Date, f64)>) -> State<f64> {
+        State {
+            id: 0,
+            parameters: vec![0.0; 4].into(),
+            prev_step: None,
+            loss: 100.0,
+            successful_turns: 0,
+            failed_turns: 0,
+            evaluations: 0,
+            last_updated_at: None,
+            losses_over_time: history,
+        }
+    }
+
+    #[test]
+    fn update_best_loss_appends_until_full() {
+        let mut state = state_with_history(vec![]);
+
+        for i in 0..64 {
+            state.update_best_loss(vec![i as f64; 4], 64.0 - i as f64, vec![1.0; 4]);
+        }
+
+        assert_eq!(state.losses_over_time.len(), 64);
+        assert_eq!(state.losses_over_time.last().unwrap().1, 1.0);
+        assert_eq!(state.successful_turns, 64);
+        assert_eq!(state.loss, 1.0);
+        assert_eq!(*state.parameters, [63.0; 4]);
+        assert_eq!(state.prev_step.as_deref(), Some(&[1.0; 4][..]));
+    }
+
+    #[test]
+    fn update_best_loss_keeps_recent_checkpoints_when_full() {
+        let now = Date::now();
+        let history = (0..64).map(|i| (now.add_hms(0, 0, i - 64), 100.0 - i as f64)).collect::<Vec<_>>();
+        let mut state = state_with_history(history.clone());
+
+        // the last checkpoint is a second old: the history doesn't change
+        for _ in 0..3 {
+            state.update_best_loss(vec![0.0; 4], 0.5, vec![1.0; 4]);
+        }
+
+        assert_eq!(state.losses_over_time.len(), 64);
+        assert_eq!(state.losses_over_time.last().unwrap().1, history.last().unwrap().1);
+        assert_eq!(state.losses_over_time[0].1, history[0].1);
+
+        // but the state itself is updated
+        assert_eq!(state.loss, 0.5);
+    }
+
+    #[test]
+    fn update_best_loss_drops_oldest_checkpoint_when_full() {
+        let now = Date::now();
+        let history = (0..64).map(|i| (now.add_hms(-2, i, 0), 100.0 - i as f64)).collect::<Vec<_>>();
+        let mut state = state_with_history(history.clone());
+
+        // the last checkpoint is more than 3 minutes old
+        state.update_best_loss(vec![0.0; 4], 0.5, vec![1.0; 4]);
+
+        assert_eq!(state.losses_over_time.len(), 64);
+        assert_eq!(state.losses_over_time.last().unwrap().1, 0.5);
+        assert_eq!(state.losses_over_time[62].1, history[63].1);
+        assert_eq!(state.losses_over_time[0].1, history[1].1);
+
+        // the new checkpoint is recent, so the next update doesn't add one
+        state.update_best_loss(vec![0.0; 4], 0.25, vec![1.0; 4]);
+
+        assert_eq!(state.losses_over_time.len(), 64);
+        assert_eq!(state.losses_over_time.last().unwrap().1, 0.5);
+    }
+
+    #[test]
+    fn apply_delta_to_base() {
+        let mut state = state_with_history(vec![]);
+        state.prev_step = Some(vec![1.0; 4].into());
+
+        state.apply_delta(&[1.0; 4], &[1, 3], &[0.5, -0.25], 50.0);
+
+        assert_eq!(*state.parameters, [1.0, 1.5, 1.0, 0.75]);
+        assert_eq!(state.prev_step.as_deref(), Some(&[0.0, 0.5, 0.0, -0.25][..]));
+        assert_eq!(state.loss, 50.0);
+        assert_eq!(state.successful_turns, 1);
+    }
+}
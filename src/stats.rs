@@ -0,0 +1,279 @@
+use std::time::{Duration, Instant};
+
+// Counters of a worker. A worker sends the counters it accumulated since its last report
+// with every `MessageToMain`, and the master merges them.
+#[derive(Clone, Default)]
+pub struct EvalStats {
+    // the number of calls to `f`
+    pub evaluations: usize,
+
+    // total time spent inside `f`
+    pub eval_time: Duration,
+
+    // the number of `WithGradientResult`s that the master accepted
+    pub acceptances: usize,
+
+    // the number of `WithGradientResult`s that the master rejected + `WithGradientResultFailure`s
+    pub failures: usize,
+
+    // the number of calls to `f` that panicked (or failed, if `f` is an external process) or timed out
+    // these evaluations are counted in `evaluations`, too
+    pub panics: usize,
+    pub timeouts: usize,
+
+    // the number of calls to `f` that returned NaN or infinity
+    // these candidates are rejected
+    pub non_finite: usize,
+}
+
+impl EvalStats {
+    pub fn merge(&mut self, other: &EvalStats) {
+        self.evaluations += other.evaluations;
+        self.eval_time += other.eval_time;
+        self.acceptances += other.acceptances;
+        self.failures += other.failures;
+        self.panics += other.panics;
+        self.timeouts += other.timeouts;
+        self.non_finite += other.non_finite;
+    }
+
+    // in microseconds
+    pub fn average_eval_time(&self) -> f64 {
+        if self.evaluations == 0 {
+            0.0
+        }
+
+        else {
+            self.eval_time.as_micros() as f64 / self.evaluations as f64
+        }
+    }
+
+    pub fn pretty_print(&self) -> String {
+        format!(
+            "evaluations: {}, time in f: {:.3}s ({:.1}us/eval), accepted: {}, failed: {}{}",
+            self.evaluations,
+            self.eval_time.as_secs_f64(),
+            self.average_eval_time(),
+            self.acceptances,
+            self.failures,
+            if self.panics > 0 || self.timeouts > 0 || self.non_finite > 0 {
+                format!(", panics: {}, timeouts: {}, non-finite losses: {}", self.panics, self.timeouts, self.non_finite)
+            } else {
+                String::new()
+            },
+        )
+    }
+}
+
+// It's owned by the master.
+pub struct Stats {
+    pub started_at: Instant,
+    pub global: EvalStats,
+
+    // indexed by channels
+    pub per_worker: Vec<EvalStats>,
+
+    // budget
+    pub max_evaluations: Option<usize>,
+    pub target_loss: Option<f64>,
+
+    // the number of evaluations it took to reach `target_loss`
+    pub evaluations_to_target: Option<usize>,
+    pub best_loss: Option<f64>,
+}
+
+impl Stats {
+    pub fn new(
+        num_workers: usize,
+        max_evaluations: Option<usize>,
+        target_loss: Option<f64>,
+    ) -> Self {
+        Stats {
+            started_at: Instant::now(),
+            global: EvalStats::default(),
+            per_worker: vec![EvalStats::default(); num_workers],
+            max_evaluations,
+            target_loss,
+            evaluations_to_target: None,
+            best_loss: None,
+        }
+    }
+
+    pub fn add_worker_stats(&mut self, worker_index: usize, stats: &EvalStats) {
+        self.per_worker[worker_index].merge(stats);
+        self.global.merge(stats);
+    }
+
+    pub fn add_acceptance(&mut self, worker_index: usize) {
+        self.per_worker[worker_index].acceptances += 1;
+        self.global.acceptances += 1;
+    }
+
+    pub fn add_failure(&mut self, worker_index: usize) {
+        self.per_worker[worker_index].failures += 1;
+        self.global.failures += 1;
+    }
+
+    pub fn update_best_loss(&mut self, loss: f64) {
+        if self.best_loss.map(|best| loss < best).unwrap_or(true) {
+            self.best_loss = Some(loss);
+        }
+
+        if let Some(target_loss) = self.target_loss {
+            if self.evaluations_to_target.is_none() && loss <= target_loss {
+                self.evaluations_to_target = Some(self.global.evaluations);
+            }
+        }
+    }
+
+    pub fn budget_exhausted(&self) -> bool {
+        match self.max_evaluations {
+            Some(max_evaluations) => self.global.evaluations >= max_evaluations,
+            None => false,
+        }
+    }
+
+    // None if there's no budget
+    pub fn remaining_evaluations(&self) -> Option<usize> {
+        self.max_evaluations.map(|max_evaluations| max_evaluations.saturating_sub(self.global.evaluations))
+    }
+
+    pub fn target_reached(&self) -> bool {
+        self.evaluations_to_target.is_some()
+    }
+
+    // evaluations per second (wall clock)
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+
+        if elapsed == 0.0 {
+            0.0
+        }
+
+        else {
+            self.global.evaluations as f64 / elapsed
+        }
+    }
+
+    pub fn pretty_print_one_line(&self) -> String {
+        format!(
+            "{}, {:.1} evals/s, best loss: {}{}",
+            self.global.pretty_print(),
+            self.throughput(),
+            self.best_loss.map(|loss| format!("{loss}")).unwrap_or_else(|| String::from("None")),
+            if let Some(evaluations) = self.evaluations_to_target {
+                format!(", evaluations to target: {evaluations}")
+            } else {
+                String::new()
+            },
+        )
+    }
+
+    pub fn pretty_print(&self) -> String {
+        format!(
+            "elapsed: {} seconds\n{}\nthroughput: {:.1} evals/s\nbudget: {}\ntarget loss: {}{}\nbest loss: {}\n{}",
+            self.started_at.elapsed().as_secs(),
+            self.global.pretty_print(),
+            self.throughput(),
+            match self.max_evaluations {
+                Some(max_evaluations) => format!(
+                    "{} / {max_evaluations} evaluations ({} remaining)",
+                    self.global.evaluations,
+                    self.remaining_evaluations().unwrap_or(0),
+                ),
+                None => String::from("unlimited"),
+            },
+            self.target_loss.map(|loss| format!("{loss}")).unwrap_or_else(|| String::from("None")),
+            if let Some(evaluations) = self.evaluations_to_target {
+                format!(" (reached after {evaluations} evaluations)")
+            } else {
+                String::new()
+            },
+            self.best_loss.map(|loss| format!("{loss}")).unwrap_or_else(|| String::from("None")),
+            self.per_worker.iter().enumerate().map(
+                |(index, stats)| format!("  worker {index}: {}", stats.pretty_print())
+            ).collect::<Vec<_>>().join("\n"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn worker_stats(evaluations: usize, eval_time_ms: u64, panics: usize) -> EvalStats {
+        EvalStats {
+            evaluations,
+            eval_time: Duration::from_millis(eval_time_ms),
+            panics,
+            ..EvalStats::default()
+        }
+    }
+
+    #[test]
+    fn counting() {
+        let mut stats = Stats::new(2, Some(100), Some(0.5));
+        stats.add_worker_stats(0, &worker_stats(10, 20, 0));
+        stats.add_worker_stats(1, &worker_stats(20, 10, 1));
+        stats.add_worker_stats(0, &worker_stats(5, 10, 0));
+        stats.add_acceptance(1);
+        stats.add_failure(0);
+        stats.add_failure(0);
+
+        assert_eq!(stats.global.evaluations, 35);
+        assert_eq!(stats.global.eval_time, Duration::from_millis(40));
+        assert_eq!(stats.global.panics, 1);
+        assert_eq!((stats.global.acceptances, stats.global.failures), (1, 2));
+        assert_eq!(stats.per_worker[0].evaluations, 15);
+        assert_eq!((stats.per_worker[0].acceptances, stats.per_worker[0].failures), (0, 2));
+        assert_eq!(stats.per_worker[1].evaluations, 20);
+        assert_eq!((stats.per_worker[1].acceptances, stats.per_worker[1].failures), (1, 0));
+
+        // 30ms over 15 evaluations
+        assert_eq!(stats.per_worker[0].average_eval_time(), 2000.0);
+
+        // the first loss at or below the target records the evaluations
+        stats.update_best_loss(1.0);
+        assert_eq!((stats.best_loss, stats.evaluations_to_target), (Some(1.0), None));
+        stats.update_best_loss(0.5);
+        stats.add_worker_stats(1, &worker_stats(65, 0, 0));
+        stats.update_best_loss(0.25);
+        assert_eq!((stats.best_loss, stats.evaluations_to_target), (Some(0.25), Some(35)));
+        assert!(stats.target_reached());
+        assert!(stats.budget_exhausted());
+    }
+
+    #[test]
+    fn rates_without_time_or_evaluations() {
+        let mut stats = Stats::new(1, None, None);
+
+        // `elapsed()` saturates to 0 for an instant in the future
+        stats.started_at = Instant::now() + Duration::from_secs(3600);
+        stats.add_worker_stats(0, &worker_stats(10, 0, 0));
+        assert_eq!(stats.throughput(), 0.0);
+
+        assert_eq!(EvalStats::default().average_eval_time(), 0.0);
+        assert!(!stats.budget_exhausted());
+        assert_eq!(stats.remaining_evaluations(), None);
+    }
+
+    #[test]
+    fn pretty_print_budget() {
+        let mut stats = Stats::new(1, Some(100), Some(0.5));
+        stats.add_worker_stats(0, &worker_stats(30, 0, 0));
+        let printed = stats.pretty_print();
+        assert!(printed.contains("budget: 30 / 100 evaluations (70 remaining)"), "{printed}");
+        assert!(printed.contains("target loss: 0.5\n"), "{printed}");
+        assert!(printed.contains("best loss: None"), "{printed}");
+
+        // the remaining evaluations don't go below 0
+        stats.add_worker_stats(0, &worker_stats(80, 0, 0));
+        stats.update_best_loss(0.25);
+        let printed = stats.pretty_print();
+        assert!(printed.contains("budget: 110 / 100 evaluations (0 remaining)"), "{printed}");
+        assert!(printed.contains("target loss: 0.5 (reached after 110 evaluations)"), "{printed}");
+
+        let printed = Stats::new(1, None, None).pretty_print();
+        assert!(printed.contains("budget: unlimited\n"), "{printed}");
+    }
+}
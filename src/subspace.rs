@@ -0,0 +1,91 @@
+// Block-coordinate search for very high dimensions (`Config.subspace`).
+//
+// In the full mode, every candidate of `TryWithGradient` perturbs every parameter, and the worker
+// sends the whole vector back. In a subspace mode, each candidate perturbs a few coordinates:
+// the worker changes them in place, evaluates, and restores them, so it never clones the vector.
+// The best candidate goes back as a sparse delta (`MessageToMain::SparseResult`).
+//
+// The master keeps the parameters that it sent until the result arrives, and applies the delta to them.
+// If another worker updated the state in the meantime, the result still replaces the state if its loss is lower,
+// like a dense result does.
+//
+// The parameter groups apply like in the full mode: a frozen group never moves, and each group's
+// part of a candidate is rescaled to the group's step size, with the group's momentum. Momentum only
+// comes from the coordinates that the previous step moved: with `RandomSubset`, consecutive steps
+// rarely share coordinates, so most candidates are fresh random steps of the group's step size.
+
+use rand::{seq::index::sample, Rng};
+
+// `Config.subspace` picks one
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subspace {
+    // every candidate perturbs every parameter
+    Full,
+
+    // every candidate perturbs `size` random coordinates
+    RandomSubset { size: usize },
+
+    // every candidate perturbs `size` consecutive coordinates, at a random offset
+    Block { size: usize },
+}
+
+impl Subspace {
+    pub fn validate(&self, dimension: usize) -> Result<(), String> {
+        match self {
+            Subspace::Full => Ok(()),
+            Subspace::RandomSubset { size } | Subspace::Block { size } if *size == 0 || *size > dimension => Err(format!(
+                "the size of subspace has to be 1 ~ {dimension}"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // `free` is the sorted indices of the parameters that are not frozen.
+    // The result is sorted, and it's a subset of `free`.
+    pub fn draw_coordinates<R: Rng>(&self, rng: &mut R, free: &[usize]) -> Vec<usize> {
+        match self {
+            Subspace::Full => free.to_vec(),
+            Subspace::RandomSubset { size } => {
+                let mut indices = sample(rng, free.len(), (*size).min(free.len())).into_iter().map(
+                    |index| free[index]
+                ).collect::<Vec<_>>();
+                indices.sort_unstable();
+
+                indices
+            },
+            Subspace::Block { size } => {
+                let size = (*size).min(free.len());
+                let start = rng.gen_range(0..=(free.len() - size));
+
+                free[start..(start + size)].to_vec()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn coordinates() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let free = (0..100).filter(|i| i % 10 != 0).collect::<Vec<_>>();
+
+        for _ in 0..20 {
+            let subset = Subspace::RandomSubset { size: 7 }.draw_coordinates(&mut rng, &free);
+            assert_eq!(subset.len(), 7);
+            assert!(subset.windows(2).all(|w| w[0] < w[1]));
+            assert!(subset.iter().all(|i| i % 10 != 0));
+
+            let block = Subspace::Block { size: 5 }.draw_coordinates(&mut rng, &free);
+            assert_eq!(block.len(), 5);
+            assert!(block.windows(2).all(|w| w[0] < w[1] && w[1] - w[0] <= 2));
+        }
+
+        assert_eq!(Subspace::Block { size: 200 }.draw_coordinates(&mut rng, &free), free);
+        assert!(Subspace::Block { size: 200 }.validate(100).is_err());
+        assert!(Subspace::RandomSubset { size: 0 }.validate(100).is_err());
+    }
+}
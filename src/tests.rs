@@ -0,0 +1,375 @@
+// Convergence regression tests: they run the whole pipeline (random phase and momentum walk)
+// headless, on functions whose minima are known, with fixed seeds.
+//
+// The step size never shrinks below `initial_step_size`, so the losses can't go much below
+// `initial_step_size^2`. The thresholds are set well above the losses the optimizer reaches
+// with these settings. There's only 1 worker, and every run stops at an evaluation count or
+// a target loss, so a run doesn't depend on thread scheduling or timing: the same seed gives
+// the same result (see `same_seed_same_result`).
+
+use crate::checkpoint::load_checkpoint;
+use crate::config::{default_config, Config};
+use crate::files::{remove_file, temp_path, write_string, WriteMode};
+use crate::groups::ParameterGroup;
+use crate::master;
+use crate::objective::Objective;
+use crate::samples::benchmarks::Benchmark;
+use crate::sampling::InitStrategy;
+use crate::scaling::Scaling;
+use crate::subspace::Subspace;
+
+const SEEDS: [u64; 3] = [0, 1, 2];
+
+fn headless_config(objective: Objective, seed: u64) -> Config {
+    let mut config = default_config();
+    config.objective = objective;
+    config.num_workers = 1;
+    config.iter_per_worker = 256;
+    config.initial_step_size = 0.05;
+    config.visualize = false;
+    config.write_logs_to = None;
+    config.listen_for_workers = None;
+    config.seed = Some(seed);
+
+    config
+}
+
+// minimum: 0 at (0.3, 0.3, ...)
+fn shifted_quadratic(parameters: &[f32]) -> f32 {
+    parameters.iter().map(|p| (p - 0.3) * (p - 0.3)).sum()
+}
+
+fn shifted_quadratic_f64(parameters: &[f64]) -> f64 {
+    parameters.iter().map(|p| (p - 0.3) * (p - 0.3)).sum()
+}
+
+#[test]
+fn converges_on_sphere() {
+    for seed in SEEDS {
+        let mut config = headless_config(Objective::Benchmark(Benchmark::Sphere), seed);
+        config.max_evaluations = Some(50_000);
+        config.target_loss = Some(0.05);
+
+        let summary = master::run(config);
+
+        assert!(
+            summary.evaluations_to_target.is_some(),
+            "seed {seed}: loss {} after {} evaluations",
+            summary.best_loss,
+            summary.evaluations,
+        );
+        assert!(summary.best_loss <= 0.05);
+        assert_eq!(Benchmark::Sphere.f(&summary.best_params), summary.best_loss);
+    }
+}
+
+// the same function in `f32` and in `f64`
+#[test]
+fn converges_on_native_function() {
+    for (objective, seed) in SEEDS.iter().flat_map(
+        |seed| [(Objective::Native(shifted_quadratic), *seed), (Objective::NativeF64(shifted_quadratic_f64), *seed)]
+    ) {
+        let precision = objective.precision();
+        let mut config = headless_config(objective, seed);
+        config.max_evaluations = Some(50_000);
+        config.target_loss = Some(0.05);
+
+        let summary = master::run(config);
+
+        assert!(
+            summary.evaluations_to_target.is_some(),
+            "{} seed {seed}: loss {} after {} evaluations",
+            precision.name(),
+            summary.best_loss,
+            summary.evaluations,
+        );
+        assert!(summary.best_params.iter().all(|p| (p - 0.3).abs() < 0.2));
+    }
+}
+
+#[test]
+fn same_seed_same_result() {
+    for objective in [Objective::Benchmark(Benchmark::Rosenbrock), Objective::Native(shifted_quadratic)] {
+        let runs = (0..2).map(|_| {
+            let mut config = headless_config(objective.clone(), 0);
+            config.max_evaluations = Some(10_000);
+            config.scaling = Scaling::Learned { decay: 0.9, min_scale: 0.05 };
+
+            master::run(config)
+        }).collect::<Vec<_>>();
+
+        assert_eq!(runs[0].best_params, runs[1].best_params);
+        assert_eq!(runs[0].best_loss, runs[1].best_loss);
+        assert_eq!(runs[0].evaluations, runs[1].evaluations);
+    }
+}
+
+#[test]
+fn momentum_walk_improves_random_phase() {
+    for seed in SEEDS {
+        // only the random phase: the walk stops as soon as it starts
+        let mut config = headless_config(Objective::Benchmark(Benchmark::Rosenbrock), seed);
+        config.max_evaluations = Some(1);
+        let random_phase = master::run(config);
+
+        let mut config = headless_config(Objective::Benchmark(Benchmark::Rosenbrock), seed);
+        config.max_evaluations = Some(50_000);
+        let walk = master::run(config);
+
+        assert!(
+            walk.best_loss < random_phase.best_loss * 0.75,
+            "seed {seed}: random phase {}, walk {}",
+            random_phase.best_loss,
+            walk.best_loss,
+        );
+        assert!(walk.evaluations >= 50_000);
+    }
+}
+
+#[test]
+fn dimension_comes_from_config() {
+    for dimension in [1, 5, 100] {
+        let mut config = headless_config(Objective::Benchmark(Benchmark::Sphere), 0);
+        config.dimension = Some(dimension);
+        config.max_evaluations = Some(1);
+
+        assert_eq!(master::run(config).best_params.len(), dimension);
+    }
+}
+
+#[test]
+fn dimension_comes_from_objective() {
+    let mut config = headless_config(Objective::NativeWithDimension { f: shifted_quadratic, dimension: || 5 }, 0);
+    config.dimension = None;
+    config.max_evaluations = Some(1);
+    assert_eq!(master::run(config).best_params.len(), 5);
+
+    // `dimension` has to agree with the objective
+    let mut config = headless_config(Objective::NativeWithDimension { f: shifted_quadratic, dimension: || 5 }, 0);
+    config.dimension = Some(6);
+    config.max_evaluations = Some(1);
+    assert!(std::panic::catch_unwind(|| master::run(config)).is_err());
+}
+
+#[test]
+#[should_panic(expected = "doesn't know its dimension")]
+fn dimension_is_required() {
+    let mut config = headless_config(Objective::Benchmark(Benchmark::Sphere), 0);
+    config.dimension = None;
+    config.max_evaluations = Some(1);
+
+    master::run(config);
+}
+
+#[test]
+fn resumes_from_checkpoint() {
+    let path = temp_path("resume");
+
+    let mut config = headless_config(Objective::Benchmark(Benchmark::Sphere), 0);
+    config.max_evaluations = Some(10_000);
+    config.checkpoint = Some(path.clone());
+    let first = master::run(config);
+
+    // the checkpoint has the final states of the first run
+    let saved = load_checkpoint(&path, 32).map_err(|e| e.render_error()).unwrap();
+    let saved_best = saved.iter().min_by(|a, b| a.loss.total_cmp(&b.loss)).unwrap();
+    assert_eq!(saved_best.loss, first.best_loss);
+    assert_eq!(saved_best.parameters, first.best_params);
+
+    // Without a budget, the resumed run stops before it calls `f`. Without the checkpoint, it would
+    // evaluate the random phase, so it starts exactly from the states of the first run.
+    let mut config = headless_config(Objective::Benchmark(Benchmark::Sphere), 1);
+    config.max_evaluations = Some(0);
+    config.checkpoint = Some(path.clone());
+    config.resume_from_checkpoint = true;
+    let resumed = master::run(config);
+
+    assert_eq!(resumed.evaluations, 0);
+    assert_eq!(resumed.best_loss, first.best_loss);
+    assert_eq!(resumed.best_params, first.best_params);
+
+    // a checkpoint of another dimension is rejected
+    let mut config = headless_config(Objective::Benchmark(Benchmark::Sphere), 2);
+    config.dimension = Some(7);
+    config.max_evaluations = Some(1);
+    config.checkpoint = Some(path.clone());
+    config.resume_from_checkpoint = true;
+    let result = std::panic::catch_unwind(|| master::run(config));
+
+    remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
+
+#[test]
+fn starts_from_initial_points() {
+    let path = temp_path("initial_points.csv");
+    let far_away = vec!["5.0"; 32].join(", ");
+    let optimum = vec!["0.3"; 32].join(", ");
+    write_string(&path, &format!("{far_away}\n{optimum}\n"), WriteMode::CreateOrTruncate).unwrap();
+
+    // the random phase is skipped, so the optimum is the best loss from the beginning
+    let mut config = headless_config(Objective::NativeF64(shifted_quadratic_f64), 0);
+    config.max_evaluations = Some(1);
+    config.initial_points = Some(path.clone());
+    let summary = master::run(config);
+
+    assert_eq!(summary.best_loss, 0.0);
+    assert_eq!(summary.evaluations, 2);
+
+    // the random phase adds its states to the initial points
+    let mut config = headless_config(Objective::NativeF64(shifted_quadratic_f64), 0);
+    config.max_evaluations = Some(1);
+    config.initial_points = Some(path.clone());
+    config.mix_with_random_params = true;
+    let summary = master::run(config);
+
+    assert_eq!(summary.best_loss, 0.0);
+    assert_eq!(summary.evaluations, 2 + 256);
+
+    // points of another dimension are rejected
+    let mut config = headless_config(Objective::NativeF64(shifted_quadratic_f64), 0);
+    config.dimension = Some(7);
+    config.max_evaluations = Some(1);
+    config.initial_points = Some(path.clone());
+    let result = std::panic::catch_unwind(|| master::run(config));
+
+    remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
+
+#[test]
+fn converges_with_space_filling_init() {
+    for init_strategy in [
+        InitStrategy::LatinHypercube { lower: -0.3, upper: 0.3 },
+        InitStrategy::Halton { lower: -0.3, upper: 0.3 },
+        InitStrategy::Gaussian { scale: 0.2 },
+        InitStrategy::Uniform { lower: -0.3, upper: 0.3 },
+    ] {
+        let mut config = headless_config(Objective::Benchmark(Benchmark::Sphere), 0);
+        config.init_strategy = init_strategy.clone();
+        config.max_evaluations = Some(50_000);
+        config.target_loss = Some(0.05);
+        let summary = master::run(config);
+
+        assert!(summary.evaluations_to_target.is_some(), "{init_strategy:?}: loss {}", summary.best_loss);
+    }
+}
+
+// the odd parameters are 100 times as large as the even ones
+fn badly_scaled_quadratic(parameters: &[f64]) -> f64 {
+    parameters.iter().enumerate().map(
+        |(i, p)| if i % 2 == 1 { (p / 100.0 - 0.3) * (p / 100.0 - 0.3) } else { (p - 0.3) * (p - 0.3) }
+    ).sum()
+}
+
+// only every 8th parameter changes the loss, and its minimum is 0 at 1.0
+fn sparse_quadratic(parameters: &[f64]) -> f64 {
+    parameters.iter().step_by(8).map(|p| (p - 1.0) * (p - 1.0)).sum()
+}
+
+#[test]
+fn scaling_helps_badly_scaled_objectives() {
+    for seed in SEEDS {
+        let mut config = headless_config(Objective::NativeF64(badly_scaled_quadratic), seed);
+        config.max_evaluations = Some(20_000);
+        let isotropic = master::run(config);
+
+        let mut config = headless_config(Objective::NativeF64(badly_scaled_quadratic), seed);
+        config.max_evaluations = Some(20_000);
+        config.scaling = Scaling::Manual((0..32).map(|i| if i % 2 == 1 { 100.0 } else { 1.0 }).collect());
+        let manual = master::run(config);
+
+        assert!(
+            manual.best_loss * 5.0 < isotropic.best_loss,
+            "seed {seed}: isotropic {}, manual {}",
+            isotropic.best_loss,
+            manual.best_loss,
+        );
+
+        // Learned scales keep the l2 norm of the steps, but they shrink the parameters that don't
+        // change the loss, so more of each step goes where it helps.
+        let mut config = headless_config(Objective::NativeF64(sparse_quadratic), seed);
+        config.max_evaluations = Some(5_000);
+        let isotropic = master::run(config);
+
+        let mut config = headless_config(Objective::NativeF64(sparse_quadratic), seed);
+        config.max_evaluations = Some(5_000);
+        config.scaling = Scaling::Learned { decay: 0.9, min_scale: 0.01 };
+        let learned = master::run(config);
+
+        assert!(
+            learned.best_loss < isotropic.best_loss * 0.9,
+            "seed {seed}: isotropic {}, learned {}",
+            isotropic.best_loss,
+            learned.best_loss,
+        );
+    }
+}
+
+#[test]
+fn parameter_groups_control_steps() {
+    let path = temp_path("groups.csv");
+    write_string(&path, &format!("{}\n{}\n", vec!["5.0"; 32].join(", "), vec!["4.0"; 32].join(", ")), WriteMode::CreateOrTruncate).unwrap();
+
+    let mut config = headless_config(Objective::NativeF64(shifted_quadratic_f64), 0);
+    config.max_evaluations = Some(20_000);
+    config.initial_points = Some(path.clone());
+    config.parameter_groups = vec![
+        ParameterGroup { frozen: true, ..ParameterGroup::new("frozen", 0..8) },
+        ParameterGroup { step_size: Some(0.5), step_moment: Some(0.9), ..ParameterGroup::new("fast", 8..16) },
+    ];
+    let summary = master::run(config);
+    remove_file(&path).unwrap();
+
+    // the frozen parameters are where one of the initial points was
+    let frozen = &summary.best_params[..8];
+    assert!(frozen.iter().all(|p| *p == 5.0) || frozen.iter().all(|p| *p == 4.0), "{frozen:?}");
+
+    // the group with the larger steps gets much closer to the optimum than the rest
+    let distance = |params: &[f64]| params.iter().map(|p| (p - 0.3).abs()).sum::<f64>() / params.len() as f64;
+    let (fast, rest) = (distance(&summary.best_params[8..16]), distance(&summary.best_params[16..]));
+    assert!(fast * 4.0 < rest, "fast: {fast}, rest: {rest}");
+}
+
+#[test]
+fn subspace_search_converges() {
+    for subspace in [Subspace::RandomSubset { size: 8 }, Subspace::Block { size: 8 }] {
+        let mut config = headless_config(Objective::NativeF64(shifted_quadratic_f64), 0);
+        config.max_evaluations = Some(50_000);
+        config.subspace = subspace.clone();
+        config.parameter_groups = vec![ParameterGroup { frozen: true, ..ParameterGroup::new("frozen", 0..1) }];
+        let summary = master::run(config);
+
+        // the frozen parameter stays where the random phase put it, so only the others converge
+        let loss = shifted_quadratic_f64(&summary.best_params[1..]);
+        assert!(loss < 0.05, "{subspace:?}: loss {loss}");
+    }
+}
+
+#[test]
+fn subspace_search_with_groups_and_learned_scaling() {
+    let path = temp_path("subspace_groups.csv");
+    write_string(&path, &format!("{}\n", vec!["5.0"; 32].join(", ")), WriteMode::CreateOrTruncate).unwrap();
+
+    for subspace in [Subspace::RandomSubset { size: 8 }, Subspace::Block { size: 8 }] {
+        let mut config = headless_config(Objective::NativeF64(shifted_quadratic_f64), 0);
+        config.max_evaluations = Some(20_000);
+        config.initial_points = Some(path.clone());
+        config.subspace = subspace.clone();
+        config.scaling = Scaling::Learned { decay: 0.9, min_scale: 0.05 };
+        config.parameter_groups = vec![
+            ParameterGroup { frozen: true, ..ParameterGroup::new("frozen", 0..8) },
+            ParameterGroup { step_size: Some(0.5), ..ParameterGroup::new("fast", 8..16) },
+        ];
+        let summary = master::run(config);
+
+        assert!(summary.best_params[..8].iter().all(|p| *p == 5.0), "{subspace:?}");
+
+        // the group with the larger steps gets much closer to the optimum than the rest
+        let distance = |params: &[f64]| params.iter().map(|p| (p - 0.3).abs()).sum::<f64>() / params.len() as f64;
+        let (fast, rest) = (distance(&summary.best_params[8..16]), distance(&summary.best_params[16..]));
+        assert!(fast * 4.0 < rest, "{subspace:?}: fast: {fast}, rest: {rest}");
+    }
+
+    remove_file(&path).unwrap();
+}
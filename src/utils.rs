@@ -1,49 +1,350 @@
-use crate::config::ParamType;
+use crate::float::Float;
+use rand::Rng;
 
-pub fn generate_random_params(length: usize, l2_norm: ParamType) -> Vec<ParamType> {
-    let mut result = (0..length).map(|_| rand::random::<ParamType>() - 0.5).collect::<Vec<_>>();
-    let curr_l2_norm = get_l2_norm(&result);
+// The kernels below work on `LANES` elements at a time, with a separate accumulator per lane.
+// It has no data dependency between the lanes, so the compiler turns each chunk into SIMD instructions.
+// The summation order differs from a plain loop, so the results can differ in the last bits.
+const LANES: usize = 8;
 
-    mul_k_params(&mut result, l2_norm / curr_l2_norm);
+pub fn generate_random_params<T: Float, R: Rng>(rng: &mut R, length: usize, l2_norm: T) -> Vec<T> {
+    let mut result = vec![T::ZERO; length];
+    fill_random_direction(rng, &mut result, l2_norm);
 
     result
 }
 
-pub fn get_l2_norm(params: &[ParamType]) -> ParamType {
-    let sum = params.iter().map(|p| p * p).sum::<ParamType>();
+// `generate_random_params` without an allocation: a random direction whose l2 norm is `l2_norm`
+// It sums the squares while it fills the buffer, so it reads the buffer only once more, to rescale it.
+pub fn fill_random_direction<T: Float, R: Rng>(rng: &mut R, buffer: &mut [T], l2_norm: T) {
+    let half = T::from_f64(0.5);
+    let mut lanes = [T::ZERO; LANES];
+    let mut chunks = buffer.chunks_exact_mut(LANES);
 
-    sum.sqrt()
+    for chunk in &mut chunks {
+        for i in 0..LANES {
+            let p = T::random(rng) - half;
+            chunk[i] = p;
+            lanes[i] += p * p;
+        }
+    }
+
+    let mut squares = T::ZERO;
+
+    for p in chunks.into_remainder().iter_mut() {
+        *p = T::random(rng) - half;
+        squares += *p * *p;
+    }
+
+    squares += lanes.into_iter().sum::<T>();
+    mul_k_params(buffer, l2_norm / squares.sqrt());
+}
+
+// `fill_random_direction`, but each element is multiplied by its scale before rescaling to `l2_norm`
+pub fn fill_scaled_random_direction<T: Float, R: Rng>(rng: &mut R, buffer: &mut [T], scales: &[T], l2_norm: T) {
+    fill_random_direction(rng, buffer, T::ONE);
+    mul_params(buffer, scales);
+
+    let curr_l2_norm = get_l2_norm(buffer);
+    mul_k_params(buffer, l2_norm / curr_l2_norm);
+}
+
+pub fn get_l2_norm<T: Float>(params: &[T]) -> T {
+    dot(params, params).sqrt()
+}
+
+pub fn dot<T: Float>(a: &[T], b: &[T]) -> T {
+    assert_eq!(a.len(), b.len(), "cannot multiply 2 vectors with different lengths");
+    let mut lanes = [T::ZERO; LANES];
+    let chunks_a = a.chunks_exact(LANES);
+    let chunks_b = b.chunks_exact(LANES);
+    let rest = chunks_a.remainder().iter().zip(chunks_b.remainder().iter()).map(|(a, b)| *a * *b).sum::<T>();
+
+    for (chunk_a, chunk_b) in chunks_a.zip(chunks_b) {
+        for i in 0..LANES {
+            lanes[i] += chunk_a[i] * chunk_b[i];
+        }
+    }
+
+    lanes.into_iter().sum::<T>() + rest
+}
+
+// the number of rows that `mat_vec` computes at a time
+const MAT_VEC_ROWS: usize = 4;
+
+// `out = weight × input + bias`. `weight` is row-major, with `out.len()` rows and `input.len()` columns.
+// It computes `MAT_VEC_ROWS` rows at a time, so each chunk of `input` is loaded once for all of them.
+pub fn mat_vec<T: Float>(weight: &[T], input: &[T], bias: &[T], out: &mut [T]) {
+    let columns = input.len();
+    assert_eq!(weight.len(), out.len() * columns, "the shape of the matrix doesn't match the vectors");
+    assert_eq!(bias.len(), out.len(), "the shape of the bias doesn't match the output");
+
+    let full_chunks = columns / LANES * LANES;
+    let mut out_blocks = out.chunks_exact_mut(MAT_VEC_ROWS);
+    let mut row = 0;
+
+    for out_block in &mut out_blocks {
+        let rows: [&[T]; MAT_VEC_ROWS] = std::array::from_fn(|i| &weight[((row + i) * columns)..((row + i + 1) * columns)]);
+        let mut lanes = [[T::ZERO; LANES]; MAT_VEC_ROWS];
+
+        for start in (0..full_chunks).step_by(LANES) {
+            let x = &input[start..(start + LANES)];
+
+            for (row_lanes, weight_row) in lanes.iter_mut().zip(rows.iter()) {
+                let w = &weight_row[start..(start + LANES)];
+
+                for i in 0..LANES {
+                    row_lanes[i] += w[i] * x[i];
+                }
+            }
+        }
+
+        for (i, (o, row_lanes)) in out_block.iter_mut().zip(lanes.iter()).enumerate() {
+            let rest = dot(&rows[i][full_chunks..], &input[full_chunks..]);
+            *o = row_lanes.iter().copied().sum::<T>() + rest + bias[row + i];
+        }
+
+        row += MAT_VEC_ROWS;
+    }
+
+    for (i, o) in out_blocks.into_remainder().iter_mut().enumerate() {
+        let r = row + i;
+        *o = dot(&weight[(r * columns)..((r + 1) * columns)], input) + bias[r];
+    }
+}
+
+// `mat_vec` with the rows split among `threads` threads. It spawns the threads on every call,
+// so it only pays off for big matrices.
+pub fn mat_vec_parallel<T: Float>(weight: &[T], input: &[T], bias: &[T], out: &mut [T], threads: usize) {
+    if threads <= 1 || out.len() < MAT_VEC_ROWS * 2 {
+        return mat_vec(weight, input, bias, out);
+    }
+
+    let columns = input.len();
+    assert_eq!(weight.len(), out.len() * columns, "the shape of the matrix doesn't match the vectors");
+    let rows_per_thread = out.len().div_ceil(threads).div_ceil(MAT_VEC_ROWS) * MAT_VEC_ROWS;
+
+    std::thread::scope(|scope| {
+        for (index, out) in out.chunks_mut(rows_per_thread).enumerate() {
+            let start = index * rows_per_thread;
+            let end = start + out.len();
+            let weight = &weight[(start * columns)..(end * columns)];
+            let bias = &bias[start..end];
+
+            scope.spawn(move || mat_vec(weight, input, bias, out));
+        }
+    });
 }
 
-pub fn get_distance_of_params(p1: &[ParamType], p2: &[ParamType]) -> ParamType {
+pub fn get_distance_of_params<T: Float>(p1: &[T], p2: &[T]) -> T {
     assert_eq!(p1.len(), p2.len());
-    let mut sum = 0.0;
+    let mut lanes = [T::ZERO; LANES];
+    let chunks1 = p1.chunks_exact(LANES);
+    let chunks2 = p2.chunks_exact(LANES);
+    let mut sum = chunks1.remainder().iter().zip(chunks2.remainder().iter()).map(
+        |(a, b)| (*a - *b) * (*a - *b)
+    ).sum::<T>();
 
-    for i in 0..p1.len() {
-        sum += (p1[i] - p2[i]) * (p1[i] - p2[i]);
+    for (c1, c2) in chunks1.zip(chunks2) {
+        for i in 0..LANES {
+            let d = c1[i] - c2[i];
+            lanes[i] += d * d;
+        }
     }
 
+    sum += lanes.into_iter().sum::<T>();
+
     sum.sqrt()
 }
 
-pub fn mul_k_params(params: &mut Vec<ParamType>, k: ParamType) {
-    for p in params.iter_mut() {
+pub fn mul_k_params<T: Float>(params: &mut [T], k: T) {
+    let mut chunks = params.chunks_exact_mut(LANES);
+
+    for chunk in &mut chunks {
+        for p in chunk.iter_mut() {
+            *p *= k;
+        }
+    }
+
+    for p in chunks.into_remainder().iter_mut() {
         *p *= k;
     }
 }
 
-pub fn add_params(params: &mut Vec<ParamType>, val: &[ParamType]) {
+pub fn add_params<T: Float>(params: &mut [T], val: &[T]) {
     assert_eq!(params.len(), val.len(), "cannot add 2 vectors with different lengths");
+    zip_with(params, val, |p, v| *p += v);
+}
 
-    for i in 0..params.len() {
-        params[i] += val[i];
-    }
+// `params += k * val`, in a single pass
+pub fn axpy<T: Float>(params: &mut [T], k: T, val: &[T]) {
+    assert_eq!(params.len(), val.len(), "cannot add 2 vectors with different lengths");
+    zip_with(params, val, |p, v| *p += k * v);
+}
+
+// element-wise
+pub fn mul_params<T: Float>(params: &mut [T], val: &[T]) {
+    assert_eq!(params.len(), val.len(), "cannot multiply 2 vectors with different lengths");
+    zip_with(params, val, |p, v| *p *= v);
 }
 
-pub fn sub_params(params: &mut Vec<ParamType>, val: &[ParamType]) {
+pub fn sub_params<T: Float>(params: &mut [T], val: &[T]) {
     assert_eq!(params.len(), val.len(), "cannot subtract 2 vectors with different lengths");
+    zip_with(params, val, |p, v| *p -= v);
+}
+
+// `f(params[i], val[i])` for every `i`, `LANES` elements at a time. The lengths are already checked.
+#[inline(always)]
+fn zip_with<T: Float, F: Fn(&mut T, T)>(params: &mut [T], val: &[T], f: F) {
+    let mut chunks = params.chunks_exact_mut(LANES);
+    let val_chunks = val.chunks_exact(LANES);
+    let val_rest = val_chunks.remainder();
+
+    for (chunk, val_chunk) in (&mut chunks).zip(val_chunks) {
+        for i in 0..LANES {
+            f(&mut chunk[i], val_chunk[i]);
+        }
+    }
+
+    for (p, v) in chunks.into_remainder().iter_mut().zip(val_rest.iter()) {
+        f(p, *v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn random_params_have_given_l2_norm() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for l2_norm in [0.01, 1.0, 250.0] {
+            let params = generate_random_params::<f64, _>(&mut rng, 32, l2_norm);
+
+            assert_eq!(params.len(), 32);
+            assert!((get_l2_norm(&params) - l2_norm).abs() <= l2_norm * 1e-12);
+
+            let params = generate_random_params::<f32, _>(&mut rng, 32, l2_norm as f32);
+
+            assert_eq!(params.len(), 32);
+            assert!((get_l2_norm(&params) - l2_norm as f32).abs() <= l2_norm as f32 * 1e-5);
+        }
+    }
+
+    #[test]
+    fn scaled_random_params() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut params = vec![0.0f64; 4];
+        fill_scaled_random_direction(&mut rng, &mut params, &[1.0, 0.0, 1000.0, 1e-3], 2.0);
+
+        assert!((get_l2_norm(&params) - 2.0).abs() <= 1e-12);
+        assert_eq!(params[1], 0.0);
+        assert!(params[2].abs() > 1.99);
+    }
+
+    #[test]
+    fn random_params_depend_on_rng() {
+        let p1 = generate_random_params::<f64, _>(&mut StdRng::seed_from_u64(0), 8, 1.0);
+        let p2 = generate_random_params::<f64, _>(&mut StdRng::seed_from_u64(0), 8, 1.0);
+        let p3 = generate_random_params::<f64, _>(&mut StdRng::seed_from_u64(1), 8, 1.0);
+
+        assert_eq!(p1, p2);
+        assert_ne!(p1, p3);
+    }
+
+    #[test]
+    fn l2_norm_and_distance() {
+        assert_eq!(get_l2_norm(&[3.0, 4.0]), 5.0);
+        assert_eq!(get_l2_norm::<f64>(&[]), 0.0);
+        assert_eq!(get_distance_of_params(&[1.0, 1.0], &[4.0, 5.0]), 5.0);
+        assert_eq!(get_distance_of_params(&[1.0, 2.0], &[1.0, 2.0]), 0.0);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut params = vec![1.0f32, -2.0, 3.0];
+
+        mul_k_params(&mut params, 2.0);
+        assert_eq!(params, vec![2.0, -4.0, 6.0]);
+
+        add_params(&mut params, &[1.0, 1.0, 1.0]);
+        assert_eq!(params, vec![3.0, -3.0, 7.0]);
+
+        sub_params(&mut params, &[3.0, -3.0, 7.0]);
+        assert_eq!(params, vec![0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn kernels_match_plain_loops() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // every remainder of `LANES`
+        for length in 0..(LANES * 3) {
+            let a = generate_random_params::<f64, _>(&mut rng, length, 3.0);
+            let b = generate_random_params::<f64, _>(&mut rng, length, 2.0);
+            let plain_norm = a.iter().map(|p| p * p).sum::<f64>().sqrt();
+            let plain_distance = a.iter().zip(b.iter()).map(|(p, q)| (p - q) * (p - q)).sum::<f64>().sqrt();
+
+            assert!((get_l2_norm(&a) - plain_norm).abs() <= 1e-12);
+            assert!((get_distance_of_params(&a, &b) - plain_distance).abs() <= 1e-12);
+
+            // the element-wise kernels have no reduction, so they have to match bit-exactly
+            let plain = |f: fn(f64, f64) -> f64| a.iter().zip(b.iter()).map(|(p, q)| f(*p, *q)).collect::<Vec<_>>();
+
+            let mut c = a.clone();
+            add_params(&mut c, &b);
+            assert_eq!(c, plain(|p, q| p + q));
+
+            let mut c = a.clone();
+            sub_params(&mut c, &b);
+            assert_eq!(c, plain(|p, q| p - q));
+
+            let mut c = a.clone();
+            mul_params(&mut c, &b);
+            assert_eq!(c, plain(|p, q| p * q));
+
+            let mut c = a.clone();
+            mul_k_params(&mut c, 1.5);
+            assert_eq!(c, plain(|p, _| p * 1.5));
+
+            let mut c = a.clone();
+            axpy(&mut c, 0.5, &b);
+            assert_eq!(c, plain(|p, q| p + 0.5 * q));
+        }
+    }
+
+    #[test]
+    fn mat_vec_matches_plain_loops() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // remainders of both the rows and the columns
+        for (rows, columns) in [(1, 1), (4, 8), (7, 19), (13, 64), (33, 5)] {
+            let weight = generate_random_params::<f64, _>(&mut rng, rows * columns, 10.0);
+            let input = generate_random_params::<f64, _>(&mut rng, columns, 1.0);
+            let bias = generate_random_params::<f64, _>(&mut rng, rows, 1.0);
+            let plain = (0..rows).map(
+                |r| (0..columns).map(|c| weight[r * columns + c] * input[c]).sum::<f64>() + bias[r]
+            ).collect::<Vec<_>>();
+
+            for threads in [1, 3] {
+                let mut out = vec![0.0; rows];
+                mat_vec_parallel(&weight, &input, &bias, &mut out, threads);
+
+                assert!(out.iter().zip(plain.iter()).all(|(a, b)| (a - b).abs() <= 1e-12));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn add_params_with_different_lengths() {
+        add_params::<f64>(&mut [1.0, 2.0], &[1.0]);
+    }
 
-    for i in 0..params.len() {
-        params[i] -= val[i];
+    #[test]
+    #[should_panic]
+    fn distance_with_different_lengths() {
+        get_distance_of_params::<f64>(&[1.0, 2.0], &[1.0]);
     }
 }